@@ -1,9 +1,27 @@
-use std::path::PathBuf;
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use clap_complete::engine::ArgValueCompleter;
+use ldfm::configs;
+
+use crate::{completions, output::OutputFormat};
 
 #[derive(Debug, Clone, clap::Parser)]
 pub struct Cli {
     #[arg(name = "log-level", short, long, default_value = "info")]
     pub log_level: tracing::level_filters::LevelFilter,
+    /// Path to the ldfm config file. Defaults to ~/.config/ldfm/config.toml
+    #[arg(long, short, global = true, env = "LDFM_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Repository to operate on, given by its name or by its path
+    #[arg(long, short, global = true, env = "LDFM_REPO")]
+    pub repo: Option<String>,
+    /// Output format of reporting commands
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Plain)]
+    pub format: OutputFormat,
+    /// How many files `apply` and `commit` compare and copy in parallel.
+    /// Defaults to `jobs` from the config file or the number of CPUs
+    #[arg(long, global = true, env = "LDFM_JOBS")]
+    pub jobs: Option<NonZeroUsize>,
     #[command(subcommand)]
     pub command: Command,
 }
@@ -12,39 +30,228 @@ pub struct Cli {
 pub enum Command {
     /// Initialize the local directory as a dotfiles repository.
     Init {
-        /// Path to the local directory to initialize
-        #[arg(long, short, default_value = "~/.config/dotfiles")]
-        local_path: PathBuf,
+        /// Name of the repository, used to add several repositories side by side
+        #[arg(long, short, default_value = configs::DEFAULT_REPO_NAME)]
+        name: String,
+        /// Priority of the repository. Higher priority wins when repositories track the same file
+        #[arg(long, short, default_value = "0", allow_negative_numbers = true)]
+        priority: i32,
+        /// Path to the local directory to initialize. Defaults to `--repo` or ~/.config/dotfiles
+        #[arg(long, short)]
+        local_path: Option<PathBuf>,
         /// Path to the remote git repository to use for dotfiles.
+        ///
+        /// With `--new` the repository is added as the `origin` remote instead of being cloned.
         git_repo: Option<String>,
+        /// Create a fresh dotfiles repository instead of cloning or reusing an existing one.
+        #[arg(long, default_value = "false")]
+        new: bool,
+        /// Default branch of the repository created with `--new`.
+        #[arg(long, short, default_value = "main")]
+        branch: String,
+        /// Reinitialize even if ldfm or the repository is already initialized.
+        #[arg(long, short, default_value = "false")]
+        force: bool,
     },
     /// Apply the current state of dotfiles to the local system.
     Apply {
         /// Disable pulling the latest changes from the remote repository before applying
         #[arg(long, short, default_value = "false")]
         no_pull: bool,
+        /// Apply files from this commit, branch or tag instead of the working tree of the repository
+        #[arg(long)]
+        rev: Option<String>,
     },
     /// Commit current state of dotfiles.
     Commit {
         /// Whether to push the changes to the remote repository
         #[arg(long, short, default_value = "false")]
         push: bool,
+        /// Store the tracked path in a copy specific to this host from now on
+        #[arg(long = "host-local", value_name = "PATH")]
+        host_local: Vec<PathBuf>,
     },
     /// Add a file or a directory to the tracking list.
     Track {
-        /// Path to the file or directory to track
+        /// Path to the file or directory to track, or a quoted glob pattern like
+        /// "~/.config/fish/functions/*.fish"
+        #[arg(add = ArgValueCompleter::new(completions::untracked_paths))]
         path: PathBuf,
+        /// Track only the named block of the file instead of the whole file
+        #[arg(long)]
+        block: Option<String>,
+        /// Comment prefix used for block markers
+        #[arg(long, default_value = "#", requires = "block")]
+        comment: String,
+        /// Track only the given key of a JSON, TOML, YAML or INI file.
+        /// Nested keys are separated with `/`
+        #[arg(long = "merge", value_name = "KEY", conflicts_with = "block")]
+        merge_keys: Vec<String>,
     },
-    /// Remove a file or a directory from the tracking list.
+    /// Remove a file or a directory from the tracking list and delete its repository copy.
+    ///
+    /// The file on this machine is left as is.
     Untrack {
         /// Path to the file or directory to untrack
+        #[arg(add = ArgValueCompleter::new(completions::tracked_paths))]
+        path: PathBuf,
+        /// Untrack only the named block of the file
+        #[arg(long)]
+        block: Option<String>,
+        /// Commit the removal of the repository copy
+        #[arg(long)]
+        purge: bool,
+        /// Replace the file with a real copy if it's a symlink
+        #[arg(long)]
+        restore: bool,
+    },
+    /// Remove a file or a directory from the tracking list, keeping the repository copy
+    /// and the file on this machine.
+    Forget {
+        /// Path to the file or directory to forget
+        #[arg(add = ArgValueCompleter::new(completions::tracked_paths))]
         path: PathBuf,
+        /// Forget only the named block of the file
+        #[arg(long)]
+        block: Option<String>,
+    },
+    /// Move a tracked file or directory and update the entries tracking it.
+    Mv {
+        /// Current path of the tracked file or directory
+        #[arg(add = ArgValueCompleter::new(completions::tracked_paths))]
+        from: PathBuf,
+        /// New path of the file or directory
+        to: PathBuf,
+    },
+    /// Change the key of a tracked entry and move its copy in the repository.
+    RenameKey {
+        /// Current key of the entry
+        #[arg(add = ArgValueCompleter::new(completions::entry_keys))]
+        from: String,
+        /// New key of the entry
+        to: String,
+    },
+    /// Open the repository copy of a tracked file in $VISUAL or $EDITOR and apply it.
+    Edit {
+        /// Path to the tracked file or directory
+        #[arg(add = ArgValueCompleter::new(completions::tracked_paths))]
+        path: PathBuf,
+        /// Edit only the named block of the file
+        #[arg(long)]
+        block: Option<String>,
+        /// Commit the entry after editing
+        #[arg(long)]
+        commit: bool,
+    },
+    /// Import dotfiles managed by another tool into the repository.
+    Import {
+        /// Tool the dotfiles are managed with
+        #[arg(long, value_enum)]
+        from: ImportFrom,
+        /// Stow directory, chezmoi source directory or path to the git repository
+        path: PathBuf,
+    },
+    /// Export tracked files as a tarball or a shell script recreating them.
+    Export {
+        /// Path of the exported file
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Type of the exported file. Detected from the output extension by default
+        #[arg(long = "type", value_enum)]
+        kind: Option<ExportType>,
     },
     /// List all tracked files and directories.
     List,
+    /// Show which tracked files differ from their copies in the repository.
+    Status {
+        /// Compare with this commit, branch or tag instead of the working tree of the repository
+        #[arg(long)]
+        rev: Option<String>,
+    },
+    /// List config files that are not tracked yet.
+    Unmanaged {
+        /// Directory to look for untracked files in, instead of the configured ones
+        #[arg(long = "root")]
+        roots: Vec<String>,
+        /// How deep to look into untracked directories
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        depth: Option<u16>,
+        /// Additional pattern of paths to skip
+        #[arg(long)]
+        ignore: Vec<String>,
+        /// Ask whether to track every found file
+        #[arg(long, short)]
+        interactive: bool,
+    },
+    /// Watch tracked files and commit them as soon as they change.
+    Watch {
+        /// Seconds without changes to wait before committing a burst of edits
+        #[arg(long, default_value = "2")]
+        debounce: u64,
+        /// Push committed changes at most once per this many seconds
+        #[arg(long)]
+        push_interval: Option<u64>,
+    },
+    /// Manage copies of entries specific to this host.
+    Overlay {
+        #[command(subcommand)]
+        command: OverlayCommand,
+    },
+    /// List applies recorded on this machine.
+    History,
+    /// Restore files to the state before an apply, undoing it and all later applies.
+    Rollback {
+        /// Id of the apply from `ldfm history`. Defaults to the last one
+        id: Option<u64>,
+    },
     /// Generate completions for the CLI.
     Completions {
         /// The shell to generate completions for
         shell: clap_complete::Shell,
     },
 }
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum OverlayCommand {
+    /// Replace the shared copy of a tracked file with the copy specific to this host.
+    Promote {
+        /// Tracked file or directory
+        #[arg(add = ArgValueCompleter::new(completions::tracked_paths))]
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ImportFrom {
+    Stow,
+    Chezmoi,
+    Yadm,
+    BareGit,
+}
+
+impl From<ImportFrom> for ldfm::ImportSource {
+    fn from(value: ImportFrom) -> Self {
+        match value {
+            ImportFrom::Stow => Self::Stow,
+            ImportFrom::Chezmoi => Self::Chezmoi,
+            ImportFrom::Yadm => Self::Yadm,
+            ImportFrom::BareGit => Self::BareGit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ExportType {
+    #[value(name = "tar.gz")]
+    TarGz,
+    Sh,
+}
+
+impl From<ExportType> for ldfm::ExportFormat {
+    fn from(value: ExportType) -> Self {
+        match value {
+            ExportType::TarGz => Self::TarGz,
+            ExportType::Sh => Self::Sh,
+        }
+    }
+}
@@ -1,26 +1,38 @@
-use fs_extra::dir::CopyOptions;
+use ldfm::{Ldfm, LdfmError};
 
-use crate::{configs::LdfmConfig, utils::git_pull};
+use crate::output::{self, OutputFormat};
 
-pub fn run(config: LdfmConfig, no_pull: bool) -> anyhow::Result<()> {
+pub fn run(
+    ldfm: Ldfm,
+    no_pull: bool,
+    rev: Option<String>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
     if !no_pull {
-        tracing::info!("Pulling latest changes from remote repository...");
-        git_pull(&config.local_path.display().to_string())?;
+        ldfm.pull()?;
     }
-    let repo_config = config.get_repo_config()?;
-    for (key, value) in repo_config.files.iter() {
-        let Some(to_path) = simple_expand_tilde::expand_tilde(value) else {
-            continue;
-        };
-        let from_path = config.local_path.join(repo_config.get_local_path(key));
-        tracing::info!("Copying {} -> {}", from_path.display(), to_path.display());
-        fs_extra::copy_items(
-            &[from_path],
-            to_path
-                .parent()
-                .ok_or(anyhow::anyhow!("Cannot get a parent directory."))?,
-            &CopyOptions::new().overwrite(true).copy_inside(true),
-        )?;
+    // Pulling first also fetches remote branches the revision may refer to.
+    let ldfm = match rev {
+        Some(rev) => ldfm.at_revision(&rev)?,
+        None => ldfm,
+    };
+    let plan = ldfm.plan_apply()?;
+    for conflict in &plan.conflicts {
+        tracing::warn!(
+            "Conflict: {} is tracked by both {} and {} repositories, using the one from {}.",
+            conflict.target.display(),
+            conflict.winner,
+            conflict.skipped,
+            conflict.winner
+        );
+    }
+    let mut report = output::with_progress(ldfm).apply_plan(&plan)?;
+    output::print_summary("Apply", format, &report.entries)?;
+    if !report.failures.is_empty() {
+        return Err(LdfmError::Failed(std::mem::take(&mut report.failures)).into());
+    }
+    if let Some(id) = report.history_id {
+        tracing::info!("Undo it with `ldfm rollback {}`.", id);
     }
     Ok(())
 }
@@ -0,0 +1,55 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    process::Command,
+};
+
+use ldfm::{Ldfm, LdfmError};
+
+pub fn run(ldfm: &Ldfm, path: PathBuf, block: Option<String>, commit: bool) -> anyhow::Result<()> {
+    let target = ldfm.edit_target(&path, block.as_deref())?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    tracing::info!("Editing {} with {}", target.source.display(), editor);
+    // Editors are often set with arguments, e.g. `code --wait`, so run them through the shell.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&target.source)
+        .status()
+        .map_err(|err| LdfmError::io(format!("Cannot run editor {}", editor), err))?;
+    if !status.success() {
+        return Err(LdfmError::Validation(format!(
+            "Editor exited with {}, the changes are not applied.",
+            status
+        ))
+        .into());
+    }
+    let mut report = ldfm.apply_entry(&target.repo, &target.key)?;
+    if !report.failures.is_empty() {
+        return Err(LdfmError::Failed(std::mem::take(&mut report.failures)).into());
+    }
+    for action in &report.applied {
+        tracing::info!("Applied {} to {}", action.key, action.target.display());
+    }
+    if commit {
+        let entries = BTreeMap::from([(target.repo.clone(), BTreeSet::from([target.key]))]);
+        for mut report in ldfm.commit_entries(&entries, false)? {
+            if !report.failures.is_empty() {
+                return Err(LdfmError::Failed(std::mem::take(&mut report.failures)).into());
+            }
+            if report.committed {
+                tracing::info!(
+                    "Committed {} to {} repository.",
+                    report.copied.join(", "),
+                    report.repo
+                );
+            } else {
+                tracing::info!("Nothing to commit in {} repository.", report.repo);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use ldfm::{ExportFormat, Ldfm, LdfmError};
+
+pub fn run(ldfm: &Ldfm, output: PathBuf, format: Option<ExportFormat>) -> anyhow::Result<()> {
+    let Some(format) = format.or_else(|| ExportFormat::from_path(&output)) else {
+        return Err(LdfmError::Validation(format!(
+            "Cannot detect export type of {}. Use `--type tar.gz` or `--type sh`.",
+            output.display()
+        ))
+        .into());
+    };
+    let report = ldfm.export(format, &output)?;
+    for key in &report.skipped {
+        tracing::warn!("Entry {} is not exported.", key);
+    }
+    tracing::info!("Exported {} files to {}", report.files, output.display());
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use ldfm::Ldfm;
+
+use crate::output::{self, OutputFormat};
+
+pub fn list(ldfm: &Ldfm, format: OutputFormat) -> anyhow::Result<()> {
+    output::print(format, &ldfm.history()?)
+}
+
+pub fn rollback(ldfm: &Ldfm, id: Option<u64>) -> anyhow::Result<()> {
+    let report = ldfm.rollback(id)?;
+    for path in &report.restored {
+        tracing::debug!("Restored {}", path.display());
+    }
+    for path in report.removed.iter().chain(&report.removed_dirs) {
+        tracing::debug!("Removed {}", path.display());
+    }
+    tracing::info!(
+        "Rolled back {} applies: restored {} files, removed {} files.",
+        report.undone.len(),
+        report.restored.len(),
+        report.removed.len()
+    );
+    Ok(())
+}
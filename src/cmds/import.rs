@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use ldfm::{ImportSource, Ldfm};
+
+pub fn run(ldfm: &Ldfm, source: ImportSource, path: PathBuf) -> anyhow::Result<()> {
+    let report = ldfm.import(source, &path)?;
+    tracing::info!(
+        "Imported {} files from {} into {} entries of {} repository: {}",
+        report.files,
+        source,
+        report.entries.len(),
+        report.repo,
+        report.entries.join(", ")
+    );
+    for message in &report.untranslated {
+        tracing::warn!("Not imported: {}", message);
+    }
+    if report.committed {
+        tracing::info!("Run `ldfm apply` to copy imported files to your home directory.");
+    }
+    Ok(())
+}
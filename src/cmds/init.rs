@@ -1,42 +1,14 @@
-use crate::{
-    configs::{LdfmConfig, RepoConfig},
-    utils::{git_clone, git_commit, git_lfs_pull},
-};
-use std::{io::Write, path::PathBuf};
+use std::path::Path;
+
+use ldfm::{InitOptions, Ldfm, configs::LdfmConfig};
 
 pub fn run(
-    config_path: PathBuf,
-    local_path: PathBuf,
-    git_repo: Option<String>,
+    config_path: &Path,
+    config: Option<LdfmConfig>,
+    options: InitOptions,
 ) -> anyhow::Result<()> {
-    let config = LdfmConfig {
-        local_path: std::path::absolute(&local_path)?,
-    };
-    if let Some(repo_uri) = git_repo {
-        tracing::info!("Cloning repository from {}", repo_uri);
-        git_clone(&repo_uri, &local_path.to_string_lossy())?;
-        git_lfs_pull(&local_path.to_string_lossy())?;
-    } else {
-        tracing::info!(
-            "Assuming local path {} is a git repository",
-            local_path.display()
-        );
-    };
-    tracing::info!("Writing configuration to {}", config_path.display());
-    let config_str = toml::to_string_pretty(&config)?;
-    let mut config_file = std::fs::File::create(&config_path)?;
-    config_file.write_all(config_str.as_bytes())?;
-
-    let repo_config = local_path.join("ldfm.toml");
-    if !repo_config.exists() {
-        tracing::info!(
-            "Creating repository configuration at {}",
-            repo_config.display()
-        );
-        let repo_config_content = toml::to_string_pretty(&RepoConfig::default())?;
-        let mut repo_config_file = std::fs::File::create(&repo_config)?;
-        repo_config_file.write_all(repo_config_content.as_bytes())?;
-        git_commit(&local_path.to_string_lossy(), "Initialized ldfm")?;
-    }
+    let name = options.name.clone();
+    Ldfm::init(config_path, config, options)?;
+    tracing::info!("Repository {} is initialized.", name);
     Ok(())
 }
@@ -1,3 +1,11 @@
 pub mod apply;
+pub mod edit;
+pub mod export;
+pub mod history;
+pub mod import;
 pub mod init;
+pub mod overlay;
+pub mod status;
 pub mod track;
+pub mod unmanaged;
+pub mod watch;
@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+use ldfm::Ldfm;
+
+pub fn promote(ldfm: &Ldfm, path: PathBuf) -> anyhow::Result<()> {
+    let overlay = ldfm.promote_overlay(&path)?;
+    tracing::info!(
+        "Promoted {} from {} to the shared copy in {} repository.",
+        overlay.key,
+        overlay.host,
+        overlay.repo
+    );
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use ldfm::Ldfm;
+
+use crate::output::{self, OutputFormat};
+
+/// Show how tracked files differ from their copies in repositories.
+pub fn run(ldfm: Ldfm, rev: Option<String>, format: OutputFormat) -> anyhow::Result<()> {
+    let ldfm = match rev {
+        Some(rev) => ldfm.at_revision(&rev)?,
+        None => ldfm,
+    };
+    output::print(format, &ldfm.status()?)
+}
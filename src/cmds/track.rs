@@ -1,158 +1,177 @@
 use std::path::PathBuf;
 
-use fs_extra::dir::CopyOptions;
+use ldfm::{Ldfm, LdfmError, UntrackOptions};
 
-use crate::{
-    configs::LdfmConfig,
-    utils::{git_commit, git_push},
-};
+use crate::output::{self, OutputFormat};
 
-pub fn add(config: LdfmConfig, path: PathBuf) -> anyhow::Result<()> {
-    let mut repo_config = config.get_repo_config()?;
-    let target_path = std::path::absolute(
-        simple_expand_tilde::expand_tilde(path)
-            .ok_or(anyhow::anyhow!("Cannot expand tilde from path"))?,
-    )?;
-    tracing::info!("Tracking file: {}", target_path.display());
-    repo_config.track_file(&target_path)?;
-    std::fs::write(
-        config.get_repo_config_path(),
-        toml::to_string_pretty(&repo_config)?,
-    )
-    .map_err(|e| anyhow::anyhow!("Failed to write config file: {}", e))?;
+pub fn add(
+    ldfm: &Ldfm,
+    path: PathBuf,
+    block: Option<String>,
+    comment: &str,
+    merge_keys: &[String],
+) -> anyhow::Result<()> {
+    let outcome = match &block {
+        Some(block) => ldfm.track_block(&path, block, comment)?,
+        None if !merge_keys.is_empty() => ldfm.track_merge(&path, merge_keys)?,
+        None => ldfm.track(&path)?,
+    };
+    if outcome.already_tracked {
+        tracing::info!(
+            "File {} is already tracked in {} repository as {}.",
+            outcome.target.display(),
+            outcome.repo,
+            outcome.key
+        );
+    } else {
+        tracing::info!(
+            "Tracking file in {} repository as {}: {}",
+            outcome.repo,
+            outcome.key,
+            outcome.target.display()
+        );
+    }
+    for other in &outcome.also_tracked_in {
+        tracing::warn!(
+            "File {} is also tracked in {} repository.",
+            outcome.target.display(),
+            other
+        );
+    }
     Ok(())
 }
 
-pub fn remove(config: LdfmConfig, path: PathBuf) -> anyhow::Result<()> {
-    let mut repo_config = config.get_repo_config()?;
-    let target_path = std::path::absolute(
-        simple_expand_tilde::expand_tilde(path)
-            .ok_or(anyhow::anyhow!("Cannot expand tilde from path"))?,
-    )?;
-    tracing::info!("Untracking file: {}", target_path.display());
-    if let Some(removed_file) = repo_config.untrack_file(&target_path)? {
-        let full_file_path = config
-            .local_path
-            .join(repo_config.get_local_path(&removed_file));
-        if full_file_path.exists() {
-            tracing::info!(
-                "Removing file from local path: {}",
-                full_file_path.display()
-            );
-            std::fs::remove_dir_all(full_file_path).ok();
-        } else {
-            tracing::warn!(
-                "File {} does not exist in local path, skipping removal.",
-                full_file_path.display()
-            );
-        }
+/// Untrack a file and report every change made to the repository and this machine.
+pub fn remove(ldfm: &Ldfm, path: PathBuf, options: UntrackOptions) -> anyhow::Result<()> {
+    let Some(outcome) = ldfm.untrack_with(&path, &options)? else {
+        tracing::warn!("File {} is not tracked.", path.display());
+        return Ok(());
+    };
+    tracing::info!(
+        "Removed {} from {} repository config: {}",
+        outcome.key,
+        outcome.repo,
+        outcome.target.display()
+    );
+    match (&outcome.restored_from, options.restore) {
+        (Some(source), _) => tracing::info!(
+            "Replaced symlink {} with a copy of {}.",
+            outcome.target.display(),
+            source.display()
+        ),
+        (None, true) => tracing::info!(
+            "{} is not a symlink, left it as is.",
+            outcome.target.display()
+        ),
+        (None, false) => tracing::info!("Left {} untouched.", outcome.target.display()),
+    }
+    match &outcome.removed_copy {
+        Some(copy) => tracing::info!("Deleted repository copy {}.", copy.display()),
+        None if options.keep_copy => tracing::info!(
+            "Kept the repository copy of {}. It's removed on the next commit, its history stays in git.",
+            outcome.key
+        ),
+        None => tracing::info!("{} had no copy in the repository.", outcome.key),
+    }
+    if outcome.committed {
+        tracing::info!("Committed the removal to {} repository.", outcome.repo);
+    } else if options.commit {
+        tracing::info!("Nothing to commit in {} repository.", outcome.repo);
     }
-    std::fs::write(
-        config.get_repo_config_path(),
-        toml::to_string_pretty(&repo_config)?,
-    )?;
     Ok(())
 }
 
-pub fn list(config: LdfmConfig) -> anyhow::Result<()> {
-    let repo_config = config.get_repo_config()?;
-    if repo_config.files.is_empty() {
-        tracing::info!("No files are currently tracked.");
-    } else {
-        tracing::info!("Tracked files:");
-        for (_, value) in &repo_config.files {
-            println!("{}", value);
-        }
+pub fn move_target(ldfm: &Ldfm, from: PathBuf, to: PathBuf) -> anyhow::Result<()> {
+    let outcome = ldfm.move_target(&from, &to)?;
+    if !outcome.moved_live {
+        tracing::warn!(
+            "File {} does not exist, updating tracked paths only.",
+            outcome.from.display()
+        );
     }
+    tracing::info!(
+        "Moved {} to {}, updated {} in {} repository.",
+        outcome.from.display(),
+        outcome.to.display(),
+        outcome.keys.join(", "),
+        outcome.repo
+    );
     Ok(())
 }
 
-pub fn sync(config: LdfmConfig, push: bool) -> anyhow::Result<()> {
-    let repo_config = config.get_repo_config()?;
-    let mut dotfiles_path = config.local_path.clone();
-    if let Some(df_root) = &repo_config.root {
-        dotfiles_path = dotfiles_path.join(df_root);
-        std::fs::remove_dir_all(&dotfiles_path)?;
-        std::fs::create_dir_all(&dotfiles_path)?;
-    } else {
-        dotfiles_path = config.local_path.clone();
-        for entry in std::fs::read_dir(&dotfiles_path)? {
-            let entry = entry?.path();
-            let Some(file_name) = entry.file_name() else {
-                continue;
-            };
-            if [".git", "ldfm.toml"].contains(&file_name.to_string_lossy().as_ref()) {
-                continue;
-            }
-            if entry.is_dir() {
-                std::fs::remove_dir_all(entry)?;
-            } else {
-                std::fs::remove_file(entry)?;
-            }
-        }
+pub fn rename_key(ldfm: &Ldfm, from: &str, to: &str) -> anyhow::Result<()> {
+    let outcome = ldfm.rename_key(from, to)?;
+    tracing::info!(
+        "Renamed {} to {} in {} repository.",
+        outcome.from,
+        outcome.to,
+        outcome.repo
+    );
+    for copy in &outcome.moved_copies {
+        tracing::debug!("Moved copy to {}", copy.display());
     }
-    let df_contents = fs_extra::dir::get_dir_content(&dotfiles_path)?;
-    // Clean up the dotfiles directory by removing files and directories
-    let git_dir = config.local_path.join(".git").display().to_string();
-    let ldfm_conig = config.local_path.join("ldfm.toml").display().to_string();
-    df_contents
-        .files
-        .iter()
-        .filter(|filepath| {
-            // We filter out files that are in the .git directory or the ldfm.toml config file
-            !(filepath.starts_with(git_dir.as_str()) || filepath.starts_with(ldfm_conig.as_str()))
-        })
-        .for_each(|file| {
-            tracing::debug!("Removing file: {}", file);
-            fs_extra::remove_items(&[file]).ok();
-        });
-    df_contents
-        .directories
-        .iter()
-        .filter(|dir| {
-            // We filter out files that are in the .git directory or the dotfiles directory iteslf.
-            !(dir.starts_with(git_dir.as_str()) || dir == &&config.local_path.display().to_string())
-        })
-        .for_each(|dir| {
-            tracing::info!("Removing directory: {}", dir);
-            fs_extra::remove_items(&[dir]).ok();
-        });
-    std::fs::create_dir_all(dotfiles_path)?;
-    for (key, value) in &repo_config.files {
-        let target_path = config.local_path.join(repo_config.get_local_path(key));
-        let actual_path = simple_expand_tilde::expand_tilde(&value)
-            .ok_or(anyhow::anyhow!("Cannot get home directory"))?;
-        if !actual_path.exists() {
-            tracing::warn!(
-                "File {} does not exist at the expected path: {}",
-                key,
-                actual_path.display()
-            );
+    Ok(())
+}
+
+pub fn list(ldfm: &Ldfm, format: OutputFormat) -> anyhow::Result<()> {
+    let reports = ldfm.list()?;
+    if reports.is_empty() && format == OutputFormat::Plain {
+        tracing::info!("No files are currently tracked.");
+    }
+    output::print(format, &reports)
+}
+
+/// Commit the current state of dotfiles to every selected repository.
+///
+/// Paths in `host_local` are committed to copies specific to this host.
+pub fn sync(
+    ldfm: &Ldfm,
+    push: bool,
+    host_local: &[PathBuf],
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    for path in host_local {
+        let overlay = ldfm.make_host_local(path)?;
+        tracing::info!(
+            "Storing {} in {} repository as specific to {}.",
+            overlay.key,
+            overlay.repo,
+            overlay.host
+        );
+    }
+    let mut entries = Vec::new();
+    let mut failures = Vec::new();
+    let mut errors = Vec::new();
+    for mut report in output::with_progress(ldfm.clone()).commit(push)? {
+        if let Some(error) = report.error.take() {
+            errors.push((report.repo, error));
             continue;
         }
-        if let Some(parent) = target_path.parent() {
-            std::fs::create_dir_all(parent).ok();
-            if !parent.exists() {
-                tracing::info!("Creating directory for target path: {}", parent.display());
-                std::fs::create_dir_all(parent)?;
-            }
+        if report.committed {
+            tracing::info!(
+                "Committed {} entries to {} repository.",
+                report.copied.len(),
+                report.repo
+            );
+        } else {
+            tracing::info!("Nothing to commit in {} repository.", report.repo);
+        }
+        if report.pushed {
+            tracing::info!("Pushed {} repository.", report.repo);
         }
-        tracing::info!(
-            "Copying file from {} to {}",
-            actual_path.display(),
-            target_path.display()
-        );
-        fs_extra::copy_items(
-            &[actual_path],
-            target_path.parent().unwrap(),
-            &CopyOptions::new().overwrite(true).copy_inside(true),
-        )?;
+        entries.append(&mut report.entries);
+        failures.append(&mut report.failures);
+    }
+    output::print_summary("Commit", format, &entries)?;
+    let mut errors = errors.into_iter();
+    if let Some((_, error)) = errors.next() {
+        for (repo, error) in errors {
+            tracing::error!("Cannot commit {} repository: {}", repo, error);
+        }
+        return Err(error.into());
     }
-    let repo_path = config.local_path.display().to_string();
-    git_commit(&repo_path, "Dotfiles sync.")?;
-    if push {
-        tracing::info!("Pushing changes to remote repository.");
-        git_push(&repo_path)?;
+    if !failures.is_empty() {
+        return Err(LdfmError::Failed(failures).into());
     }
     Ok(())
 }
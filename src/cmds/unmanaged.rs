@@ -0,0 +1,40 @@
+use std::io::{BufRead, Write};
+
+use ldfm::{Ldfm, configs::UnmanagedConfig};
+
+use crate::output::{self, OutputFormat};
+
+/// List untracked files, or offer to track them one by one.
+pub fn run(
+    ldfm: &Ldfm,
+    options: &UnmanagedConfig,
+    interactive: bool,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let entries = ldfm.unmanaged(options)?;
+    if !interactive {
+        return output::print(format, &entries);
+    }
+    let mut lines = std::io::stdin().lock().lines();
+    for entry in entries {
+        eprint!("Track {} ({})? [y/N/q] ", entry.path, entry.entry_type);
+        std::io::stderr().flush()?;
+        let Some(answer) = lines.next().transpose()? else {
+            break;
+        };
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => {
+                let outcome = ldfm.track(entry.path.as_ref())?;
+                tracing::info!(
+                    "Tracking file in {} repository as {}: {}",
+                    outcome.repo,
+                    outcome.key,
+                    outcome.target.display()
+                );
+            }
+            "q" | "quit" => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
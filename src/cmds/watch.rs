@@ -0,0 +1,111 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
+};
+
+use ldfm::{Ldfm, LdfmError};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// Watch tracked files and commit them as soon as they change.
+///
+/// Bursts of changes are collected until nothing changes for `debounce`.
+/// If `push_interval` is set, new commits are pushed at most once per interval.
+pub fn run(ldfm: &Ldfm, debounce: Duration, push_interval: Option<Duration>) -> anyhow::Result<()> {
+    let targets = ldfm.watch_targets()?;
+    let repo_paths = ldfm
+        .repositories()
+        .iter()
+        .map(|repo| repo.local_path.clone())
+        .collect::<Vec<_>>();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut watched = HashSet::new();
+    for target in &targets {
+        // Files are watched through their parent directories,
+        // because editors often replace files instead of writing to them.
+        let (path, mode) = if target.path.is_dir() {
+            (target.path.as_path(), RecursiveMode::Recursive)
+        } else if let Some(parent) = target.path.parent().filter(|parent| parent.is_dir()) {
+            (parent, RecursiveMode::NonRecursive)
+        } else {
+            tracing::warn!(
+                "Cannot watch {}: its directory does not exist.",
+                target.path.display()
+            );
+            continue;
+        };
+        if watched.insert(path.to_path_buf()) {
+            watcher.watch(path, mode)?;
+        }
+    }
+    if watched.is_empty() {
+        return Err(
+            LdfmError::Validation("There are no tracked files to watch.".to_string()).into(),
+        );
+    }
+    tracing::info!("Watching {} tracked entries for changes.", targets.len());
+
+    let mut changed: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut last_change: Option<Instant> = None;
+    let mut last_push = Instant::now();
+    let mut unpushed = false;
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(Ok(event)) => {
+                if !matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    continue;
+                }
+                for path in &event.paths {
+                    if repo_paths.iter().any(|repo| path.starts_with(repo)) {
+                        continue;
+                    }
+                    for target in targets.iter().filter(|target| target.matches(path)) {
+                        tracing::debug!("{} changed: {}", target.key, path.display());
+                        changed
+                            .entry(target.repo.clone())
+                            .or_default()
+                            .insert(target.key.clone());
+                        last_change = Some(Instant::now());
+                    }
+                }
+            }
+            Ok(Err(err)) => tracing::warn!("Watch error: {}", err),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if last_change.is_some_and(|at| at.elapsed() >= debounce) {
+            last_change = None;
+            match ldfm.commit_entries(&std::mem::take(&mut changed), false) {
+                Ok(reports) => {
+                    for failure in reports.iter().flat_map(|report| &report.failures) {
+                        tracing::error!("Failed to commit {}", failure);
+                    }
+                    for report in reports.iter().filter(|report| report.committed) {
+                        tracing::info!(
+                            "Committed {} to {} repository.",
+                            report.copied.join(", "),
+                            report.repo
+                        );
+                        unpushed = true;
+                    }
+                }
+                Err(err) => tracing::error!("Failed to commit changes: {}", err),
+            }
+        }
+        if let Some(interval) = push_interval
+            && unpushed
+            && last_push.elapsed() >= interval
+        {
+            last_push = Instant::now();
+            match ldfm.push() {
+                Ok(()) => unpushed = false,
+                Err(err) => tracing::error!("Failed to push changes: {}", err),
+            }
+        }
+    }
+    Ok(())
+}
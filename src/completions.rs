@@ -0,0 +1,114 @@
+//! Dynamic completion of tracked paths and entry keys.
+//!
+//! Completers only see the word being completed, so the config is located the same way
+//! as without arguments: from `LDFM_CONFIG`, `LDFM_REPO` and the default config path.
+//! Any error simply results in no candidates.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use clap_complete::engine::{CompletionCandidate, PathCompleter, ValueCompleter};
+use ldfm::{Ldfm, configs::LdfmConfig, expand_tilde, report::EntryReport};
+
+fn tracked_entries() -> Vec<EntryReport> {
+    let load = || -> ldfm::Result<Vec<EntryReport>> {
+        let config_file = match std::env::var_os("LDFM_CONFIG") {
+            Some(path) => PathBuf::from(path),
+            None => match dirs::config_dir() {
+                Some(dir) => dir.join("ldfm").join("config.toml"),
+                None => return Ok(Vec::new()),
+            },
+        };
+        if !config_file.exists() {
+            return Ok(Vec::new());
+        }
+        let mut config = LdfmConfig::load(&config_file)?;
+        let mut repo = None;
+        if let Ok(selector) = std::env::var("LDFM_REPO") {
+            let (selected_config, name) = LdfmConfig::select_repo(Some(config), &selector)?;
+            config = selected_config;
+            repo = Some(name);
+        }
+        Ldfm::open(config)?.select(repo)?.list()
+    };
+    load().unwrap_or_default()
+}
+
+/// Absolute form of a path typed on the command line.
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let path = expand_tilde(path)?;
+    let path = if path.is_relative() {
+        std::env::current_dir().ok()?.join(path)
+    } else {
+        path
+    };
+    Some(path)
+}
+
+/// Complete paths of tracked entries.
+///
+/// Paths are offered as written in the repository config, or expanded when the current
+/// word is an absolute path.
+pub fn tracked_paths(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    let mut paths = BTreeMap::<String, Vec<String>>::new();
+    for entry in tracked_entries() {
+        let mut target = entry.target;
+        if current.starts_with('/')
+            && let Some(expanded) = expand_tilde(&target)
+        {
+            target = expanded.to_string_lossy().into_owned();
+        }
+        if target.starts_with(current.as_ref()) {
+            paths
+                .entry(target)
+                .or_default()
+                .push(format!("{}: {}", entry.repo, entry.key));
+        }
+    }
+    paths
+        .into_iter()
+        .map(|(path, keys)| CompletionCandidate::new(path).help(Some(keys.join(", ").into())))
+        .collect()
+}
+
+/// Complete keys of tracked entries.
+pub fn entry_keys(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    let mut keys = BTreeMap::new();
+    for entry in tracked_entries() {
+        if entry.key.starts_with(current.as_ref()) {
+            keys.entry(entry.key).or_insert(entry.target);
+        }
+    }
+    keys.into_iter()
+        .map(|(key, target)| CompletionCandidate::new(key).help(Some(target.into())))
+        .collect()
+}
+
+/// Complete filesystem paths, marking the ones that are already tracked.
+pub fn untracked_paths(current: &OsStr) -> Vec<CompletionCandidate> {
+    let candidates = PathCompleter::any().complete(current);
+    if candidates.is_empty() {
+        return candidates;
+    }
+    let tracked = tracked_entries()
+        .into_iter()
+        .filter_map(|entry| expand_tilde(&entry.target))
+        .collect::<Vec<_>>();
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let is_tracked = absolute(Path::new(candidate.get_value()))
+                .is_some_and(|path| tracked.contains(&path));
+            if is_tracked {
+                candidate.help(Some("tracked".into()))
+            } else {
+                candidate
+            }
+        })
+        .collect()
+}
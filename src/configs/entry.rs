@@ -0,0 +1,479 @@
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{IoContext, LdfmError, Result},
+    merge,
+    utils::{expand_tilde, walk_dir},
+};
+
+/// A tracked entry of the repository config.
+///
+/// Whole files and directories are written as a plain path,
+/// other kinds of entries as tables:
+///
+/// ```toml
+/// [files]
+/// ".vimrc" = "~/.vimrc"
+/// ".bashrc.aliases" = { path = "~/.bashrc", block = "aliases" }
+/// "settings.json" = { path = "~/.config/Code/User/settings.json", keys = ["editor.fontSize"] }
+/// "functions" = { pattern = "~/.config/fish/functions/*.fish" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Entry {
+    /// A whole file or directory owned by ldfm.
+    Path(String),
+    /// A block of text inside a file, see [`BlockEntry`].
+    Block(BlockEntry),
+    /// Some keys of a structured config file, see [`MergeEntry`].
+    Merge(MergeEntry),
+    /// Files matching a glob pattern, see [`PatternEntry`].
+    Pattern(PatternEntry),
+}
+
+/// An entry that manages only a part of the file,
+/// leaving the rest of it to other programs.
+pub trait PartialEntry {
+    /// Path of the file on this machine.
+    fn live_path(&self) -> Result<PathBuf>;
+
+    /// Read the managed part of the file on this machine
+    /// in the form it's stored in the repository.
+    ///
+    /// Returns `None` if the file or its managed part doesn't exist.
+    fn read_live(&self) -> Result<Option<String>>;
+
+    /// Contents of the file after replacing the managed part of
+    /// its current contents `live` with the copy from the repository.
+    fn render_live(&self, live: &str, contents: &str) -> Result<String>;
+
+    /// Replace the managed part of the file on this machine with
+    /// the copy from the repository, creating the file if needed.
+    fn write_live(&self, contents: &str) -> Result<()> {
+        let path = self.live_path()?;
+        let live = read_or_empty(&path)?;
+        write_creating_parents(&path, &self.render_live(&live, contents)?)
+    }
+}
+
+/// Text between marker comments inside a file that is otherwise
+/// owned by someone else:
+///
+/// ```sh
+/// # >>> ldfm:aliases >>>
+/// alias ll='ls -l'
+/// # <<< ldfm:aliases <<<
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockEntry {
+    pub path: String,
+    /// Name of the block used in its markers.
+    pub block: String,
+    /// Prefix of comments in the file.
+    #[serde(
+        default = "BlockEntry::default_comment",
+        skip_serializing_if = "BlockEntry::is_default_comment"
+    )]
+    pub comment: String,
+}
+
+/// Keys of a JSON, TOML, YAML or INI file, while the rest of the file
+/// is left untouched. Useful for configs that mix preferences with
+/// state written by the application itself.
+///
+/// Keys are paths separated with `/`, since keys of many applications
+/// contain dots, e.g. `editor.fontSize` or `aliases/co`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeEntry {
+    pub path: String,
+    /// Paths of managed keys.
+    pub keys: Vec<String>,
+    /// Format of the file. Detected from the file extension by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<MergeFormat>,
+}
+
+/// Files matching a glob pattern like `~/.config/fish/functions/*.fish`.
+///
+/// The pattern is expanded on every commit, so new matching files
+/// are tracked automatically. `*` doesn't match `/`, use `**` to match
+/// files in nested directories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatternEntry {
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeFormat {
+    Json,
+    Toml,
+    Yaml,
+    Ini,
+}
+
+impl Entry {
+    /// Tracked path as written in the config, usually starting with `~`.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Path(path) => path,
+            Self::Block(block) => &block.path,
+            Self::Merge(merge) => &merge.path,
+            Self::Pattern(pattern) => &pattern.pattern,
+        }
+    }
+
+    /// Name of the block if the entry is a block.
+    pub fn block(&self) -> Option<&str> {
+        match self {
+            Self::Block(block) => Some(&block.block),
+            Self::Path(_) | Self::Merge(_) | Self::Pattern(_) => None,
+        }
+    }
+
+    /// The entry as a partially managed file, unless it's a whole file or directory.
+    pub fn partial(&self) -> Option<&dyn PartialEntry> {
+        match self {
+            Self::Path(_) | Self::Pattern(_) => None,
+            Self::Block(block) => Some(block),
+            Self::Merge(merge) => Some(merge),
+        }
+    }
+
+    /// Whether both entries manage the same part of the same file.
+    pub fn same_target(&self, other: &Entry) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.path() == other.path()
+            && self.block() == other.block()
+    }
+}
+
+impl PartialEq<str> for Entry {
+    fn eq(&self, other: &str) -> bool {
+        matches!(self, Self::Path(path) if path == other)
+    }
+}
+
+impl BlockEntry {
+    pub const DEFAULT_COMMENT: &str = "#";
+
+    fn default_comment() -> String {
+        Self::DEFAULT_COMMENT.to_string()
+    }
+
+    fn is_default_comment(comment: &str) -> bool {
+        comment == Self::DEFAULT_COMMENT
+    }
+
+    pub fn start_marker(&self) -> String {
+        format!("{} >>> ldfm:{} >>>", self.comment, self.block)
+    }
+
+    pub fn end_marker(&self) -> String {
+        format!("{} <<< ldfm:{} <<<", self.comment, self.block)
+    }
+
+    /// Extract contents of the block from the file contents.
+    ///
+    /// Returns `None` if the file has no such block.
+    pub fn extract(&self, contents: &str) -> Option<String> {
+        let (start, end) = self.find(contents)?;
+        Some(contents[start..end].to_string())
+    }
+
+    /// Replace contents of the block in the file contents.
+    ///
+    /// If the file has no such block, it's appended to the end of the file.
+    /// The rest of the file is left untouched.
+    pub fn replace(&self, contents: &str, block: &str) -> String {
+        let mut block = block.to_string();
+        if !block.is_empty() && !block.ends_with('\n') {
+            block.push('\n');
+        }
+        if let Some((start, end)) = self.find(contents) {
+            return format!("{}{}{}", &contents[..start], block, &contents[end..]);
+        }
+        let mut result = contents.to_string();
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        format!(
+            "{}{}\n{}{}\n",
+            result,
+            self.start_marker(),
+            block,
+            self.end_marker()
+        )
+    }
+
+    /// Byte range of the block contents between the markers.
+    fn find(&self, contents: &str) -> Option<(usize, usize)> {
+        let start_marker = self.start_marker();
+        let end_marker = self.end_marker();
+        let mut offset = 0;
+        let mut start = None;
+        for line in contents.split_inclusive('\n') {
+            let trimmed = line.trim();
+            match start {
+                None if trimmed == start_marker => start = Some(offset + line.len()),
+                Some(start) if trimmed == end_marker => return Some((start, offset)),
+                _ => {}
+            }
+            offset += line.len();
+        }
+        None
+    }
+}
+
+impl PartialEntry for BlockEntry {
+    fn live_path(&self) -> Result<PathBuf> {
+        expand_path(&self.path)
+    }
+
+    fn read_live(&self) -> Result<Option<String>> {
+        let path = self.live_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path).with_path("read", &path)?;
+        Ok(self.extract(&contents))
+    }
+
+    fn render_live(&self, live: &str, contents: &str) -> Result<String> {
+        Ok(self.replace(live, contents))
+    }
+}
+
+impl MergeEntry {
+    /// Format of the file, either configured or detected from its extension.
+    pub fn format(&self) -> Result<MergeFormat> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        let extension = std::path::Path::new(&self.path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("json") => Ok(MergeFormat::Json),
+            Some("toml") => Ok(MergeFormat::Toml),
+            Some("yaml" | "yml") => Ok(MergeFormat::Yaml),
+            Some("ini" | "cfg" | "conf") => Ok(MergeFormat::Ini),
+            _ => Err(LdfmError::Validation(format!(
+                "Cannot detect format of {}. Set `format` of the entry in ldfm.toml.",
+                self.path
+            ))),
+        }
+    }
+}
+
+impl PartialEntry for MergeEntry {
+    fn live_path(&self) -> Result<PathBuf> {
+        expand_path(&self.path)
+    }
+
+    fn read_live(&self) -> Result<Option<String>> {
+        let path = self.live_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let format = self.format()?;
+        let contents = std::fs::read_to_string(&path).with_path("read", &path)?;
+        let live = merge::parse(format, &contents).map_err(|err| parse_error(&path, err))?;
+        let managed = merge::extract(&live, &self.keys);
+        if managed
+            .as_object()
+            .is_some_and(|managed| managed.is_empty())
+        {
+            return Ok(None);
+        }
+        Ok(Some(
+            merge::serialize(format, &managed).map_err(|err| parse_error(&path, err))?,
+        ))
+    }
+
+    fn render_live(&self, live: &str, contents: &str) -> Result<String> {
+        let path = self.live_path()?;
+        let format = self.format()?;
+        let managed = merge::parse(format, contents)
+            .map_err(|err| LdfmError::Validation(format!("Cannot parse {}: {}", self.path, err)))?;
+        if merge::has_comments(format, live) {
+            return Err(LdfmError::Validation(format!(
+                "Cannot merge into {}, merging would drop its comments. \
+                 Remove them or track the whole file instead.",
+                path.display()
+            )));
+        }
+        let mut live = merge::parse(format, live).map_err(|err| parse_error(&path, err))?;
+        merge::merge(&mut live, &merge::extract(&managed, &self.keys));
+        merge::serialize(format, &live).map_err(|err| parse_error(&path, err))
+    }
+}
+
+impl PatternEntry {
+    /// Whether the path contains glob characters.
+    pub fn is_pattern(path: &str) -> bool {
+        path.contains(['*', '?', '[', '{'])
+    }
+
+    /// Directory before the first component with glob characters.
+    ///
+    /// Matching files are stored in the repository relative to it.
+    pub fn base(&self) -> Result<PathBuf> {
+        expand_path(&self.split().0)
+    }
+
+    /// Glob matching paths relative to the base directory.
+    pub fn matcher(&self) -> Result<GlobMatcher> {
+        let glob = GlobBuilder::new(&self.split().1)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| {
+                LdfmError::Validation(format!("Invalid pattern {}: {}", self.pattern, err))
+            })?;
+        Ok(glob.compile_matcher())
+    }
+
+    /// Files currently matching the pattern, relative to the base directory.
+    pub fn expand(&self) -> Result<Vec<PathBuf>> {
+        let base = self.base()?;
+        if !base.is_dir() {
+            return Ok(Vec::new());
+        }
+        let relative = self.split().1;
+        // Without `**` there's no need to look deeper than the pattern itself.
+        let depth = if relative.contains("**") {
+            usize::MAX
+        } else {
+            relative.split('/').count()
+        };
+        let matcher = self.matcher()?;
+        let mut matches = Vec::new();
+        expand_dir(&base, Path::new(""), depth, &matcher, &mut matches)?;
+        Ok(matches)
+    }
+
+    /// Split the pattern into the base directory and the relative glob.
+    fn split(&self) -> (String, String) {
+        let components = self.pattern.split('/').collect::<Vec<_>>();
+        let first_glob = components
+            .iter()
+            .position(|component| Self::is_pattern(component))
+            .unwrap_or(components.len() - 1);
+        (
+            components[..first_glob].join("/"),
+            components[first_glob..].join("/"),
+        )
+    }
+}
+
+fn expand_dir(
+    dir: &Path,
+    relative: &Path,
+    depth: usize,
+    matcher: &GlobMatcher,
+    matches: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if depth == 0 {
+        return Ok(());
+    }
+    for name in walk_dir(dir, false)? {
+        let path = dir.join(&name);
+        let relative = relative.join(&name);
+        if path.is_dir() {
+            expand_dir(&path, &relative, depth - 1, matcher, matches)?;
+        } else if matcher.is_match(&relative) {
+            matches.push(relative);
+        }
+    }
+    Ok(())
+}
+
+fn expand_path(path: &str) -> Result<PathBuf> {
+    expand_tilde(path).ok_or(LdfmError::Validation(
+        "Cannot get home directory. Set HOME or LDFM_HOME.".to_string(),
+    ))
+}
+
+fn read_or_empty(path: &Path) -> Result<String> {
+    if path.exists() {
+        std::fs::read_to_string(path).with_path("read", path)
+    } else {
+        Ok(String::new())
+    }
+}
+
+fn write_creating_parents(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_path("create", parent)?;
+    }
+    std::fs::write(path, contents).with_path("write", path)
+}
+
+fn parse_error(path: &Path, err: String) -> LdfmError {
+    LdfmError::Validation(format!("Cannot parse {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{BlockEntry, PatternEntry};
+
+    fn aliases() -> BlockEntry {
+        BlockEntry {
+            path: "~/.bashrc".to_string(),
+            block: "aliases".to_string(),
+            comment: "#".to_string(),
+        }
+    }
+
+    #[test]
+    fn extract_block() {
+        let contents = "export A=1\n# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\nexport B=2\n";
+        assert_eq!(
+            aliases().extract(contents).as_deref(),
+            Some("alias ll='ls -l'\n")
+        );
+        assert_eq!(aliases().extract("export A=1\n"), None);
+    }
+
+    #[test]
+    fn replace_block() {
+        let contents = "export A=1\n# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\nexport B=2\n";
+        assert_eq!(
+            aliases().replace(contents, "alias la='ls -a'"),
+            "export A=1\n# >>> ldfm:aliases >>>\nalias la='ls -a'\n# <<< ldfm:aliases <<<\nexport B=2\n"
+        );
+    }
+
+    #[test]
+    fn insert_missing_block() {
+        assert_eq!(
+            aliases().replace("export A=1", "alias ll='ls -l'\n"),
+            "export A=1\n# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\n"
+        );
+        assert_eq!(
+            aliases().replace("", "alias ll='ls -l'\n"),
+            "# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\n"
+        );
+    }
+
+    #[test]
+    fn pattern_base() {
+        let pattern = PatternEntry {
+            pattern: "~/.config/fish/functions/*.fish".to_string(),
+        };
+        assert_eq!(
+            pattern.base().unwrap(),
+            crate::utils::home_dir()
+                .unwrap()
+                .join(".config/fish/functions")
+        );
+        let matcher = pattern.matcher().unwrap();
+        assert!(matcher.is_match(PathBuf::from("ll.fish")));
+        assert!(!matcher.is_match(PathBuf::from("nested/ll.fish")));
+    }
+}
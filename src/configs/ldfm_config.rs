@@ -1,27 +1,270 @@
-use std::path::PathBuf;
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::configs::RepoConfig;
+use crate::{
+    configs::RepoConfig,
+    errors::{IoContext, LdfmError, Result},
+    utils::{expand_tilde, write_toml},
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Name of the repository created by `ldfm init` when no name is given.
+pub const DEFAULT_REPO_NAME: &str = "default";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LdfmConfig {
+    /// Single repository path used by configs written before named repositories.
+    ///
+    /// It's migrated into `repos` as the `default` repository on load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    local_path: Option<PathBuf>,
+    #[serde(default)]
+    pub repos: Vec<Repository>,
+    /// Where `ldfm unmanaged` looks for untracked files.
+    #[serde(default, skip_serializing_if = "UnmanagedConfig::is_default")]
+    pub unmanaged: UnmanagedConfig,
+    /// How many files `apply` and `commit` compare and copy in parallel.
+    /// Defaults to the number of CPUs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+/// Settings of `ldfm unmanaged`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnmanagedConfig {
+    /// Directories to look for untracked files in.
+    pub roots: Vec<String>,
+    /// How deep to look into untracked directories.
+    /// Directories at this depth are reported as a whole.
+    pub depth: usize,
+    /// Patterns of paths to skip, matched like `exclude` patterns
+    /// against paths relative to the home directory.
+    pub ignore: Vec<String>,
+}
+
+impl Default for UnmanagedConfig {
+    fn default() -> Self {
+        Self {
+            roots: vec!["~".to_string(), "~/.config".to_string()],
+            depth: 1,
+            ignore: [
+                ".cache",
+                ".local",
+                ".git",
+                ".DS_Store",
+                ".Trash",
+                "*.log",
+                "*_history",
+                ".config/ldfm",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl UnmanagedConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A dotfiles repository managed by ldfm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repository {
+    pub name: String,
     pub local_path: PathBuf,
+    /// Repositories with higher priority win when several of them track the same file.
+    #[serde(default)]
+    pub priority: i32,
 }
 
 impl LdfmConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(LdfmError::ConfigMissing {
+                path: path.to_path_buf(),
+            });
+        }
+        let contents = std::fs::read_to_string(path).with_path("read", path)?;
+        Self::from_toml(&contents).map_err(|source| LdfmError::ConfigParse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_toml(path, self)
+    }
+
+    pub fn from_toml(contents: &str) -> std::result::Result<Self, toml::de::Error> {
+        let mut config: LdfmConfig = toml::from_str(contents)?;
+        if let Some(local_path) = config.local_path.take() {
+            config.repos.insert(
+                0,
+                Repository {
+                    name: DEFAULT_REPO_NAME.to_string(),
+                    local_path,
+                    priority: 0,
+                },
+            );
+        }
+        Ok(config)
+    }
+
+    /// Repositories ordered from the highest priority to the lowest.
+    ///
+    /// Repositories with the same priority keep the order of the config file.
+    pub fn repositories(&self) -> Vec<&Repository> {
+        let mut repos = self.repos.iter().collect::<Vec<_>>();
+        repos.sort_by_key(|repo| std::cmp::Reverse(repo.priority));
+        repos
+    }
+
+    /// Find a repository by name.
+    ///
+    /// If no name is given, the repository with the highest priority is returned.
+    pub fn get_repo(&self, name: Option<&str>) -> Result<&Repository> {
+        match name {
+            Some(name) => {
+                self.repos
+                    .iter()
+                    .find(|repo| repo.name == name)
+                    .ok_or(LdfmError::Validation(format!(
+                        "Repository {} is not configured",
+                        name
+                    )))
+            }
+            None => self
+                .repositories()
+                .first()
+                .copied()
+                .ok_or(LdfmError::Validation(
+                    "No repositories are configured. Run `ldfm init` first.".to_string(),
+                )),
+        }
+    }
+
+    /// Repositories to operate on: the one with the given name,
+    /// or all of them ordered by priority.
+    pub fn selected_repos(&self, name: Option<&str>) -> Result<Vec<&Repository>> {
+        match name {
+            Some(name) => Ok(vec![self.get_repo(Some(name))?]),
+            None => Ok(self.repositories()),
+        }
+    }
+
+    /// Resolve a repository selected with `--repo`.
+    ///
+    /// The selector is either a name of a configured repository or a path to a repository.
+    /// A path that doesn't belong to any configured repository replaces all of them,
+    /// so ldfm can work with it even if it wasn't initialized.
+    /// Returns the config to use and the name of the selected repository.
+    pub fn select_repo(config: Option<Self>, selector: &str) -> Result<(Self, String)> {
+        let mut config = config.unwrap_or_default();
+        if config.repos.iter().any(|repo| repo.name == selector) {
+            return Ok((config, selector.to_string()));
+        }
+        let local_path = expand_tilde(selector).ok_or(LdfmError::Validation(
+            "Cannot expand tilde from path".to_string(),
+        ))?;
+        let local_path = std::path::absolute(&local_path).with_path("resolve", &local_path)?;
+        if let Some(repo) = config
+            .repos
+            .iter()
+            .find(|repo| repo.local_path == local_path)
+        {
+            let name = repo.name.clone();
+            return Ok((config, name));
+        }
+        if !local_path.join("ldfm.toml").exists() {
+            return Err(LdfmError::Validation(format!(
+                "{} is neither a configured repository nor a path to an ldfm repository",
+                selector
+            )));
+        }
+        config.repos = vec![Repository {
+            name: DEFAULT_REPO_NAME.to_string(),
+            local_path,
+            priority: 0,
+        }];
+        Ok((config, DEFAULT_REPO_NAME.to_string()))
+    }
+
+    /// Add a repository or replace the one with the same name.
+    pub fn set_repo(&mut self, repo: Repository) {
+        if let Some(existing) = self.repos.iter_mut().find(|r| r.name == repo.name) {
+            *existing = repo;
+        } else {
+            self.repos.push(repo);
+        }
+    }
+}
+
+impl Repository {
     pub fn get_repo_config_path(&self) -> PathBuf {
         self.local_path.join("ldfm.toml")
     }
 
-    pub fn get_repo_config(&self) -> anyhow::Result<RepoConfig> {
+    pub fn get_repo_config(&self) -> Result<RepoConfig> {
         let config_path = self.get_repo_config_path();
         if config_path.exists() {
-            let config_data = std::fs::read_to_string(config_path)?;
-            let repo_config = toml::from_str(&config_data)?;
-            Ok(repo_config)
+            let config_data =
+                std::fs::read_to_string(&config_path).with_path("read", &config_path)?;
+            toml::from_str(&config_data).map_err(|source| LdfmError::ConfigParse {
+                path: config_path,
+                source,
+            })
         } else {
-            anyhow::bail!("Config file not found at {}", config_path.display());
+            Err(LdfmError::ConfigMissing { path: config_path })
         }
     }
+
+    pub fn save_repo_config(&self, repo_config: &RepoConfig) -> Result<()> {
+        write_toml(&self.get_repo_config_path(), repo_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LdfmConfig;
+
+    #[test]
+    fn legacy_local_path() {
+        let config = LdfmConfig::from_toml(r#"local_path = "/tmp/dotfiles""#).unwrap();
+        assert_eq!(config.repos.len(), 1);
+        assert_eq!(config.repos[0].name, "default");
+        assert_eq!(config.repos[0].local_path.to_str(), Some("/tmp/dotfiles"));
+        assert!(!toml::to_string(&config).unwrap().starts_with("local_path"));
+    }
+
+    #[test]
+    fn repositories_priority() {
+        let config = LdfmConfig::from_toml(
+            r#"
+            [[repos]]
+            name = "company"
+            local_path = "/tmp/company"
+
+            [[repos]]
+            name = "personal"
+            local_path = "/tmp/personal"
+            priority = 10
+            "#,
+        )
+        .unwrap();
+        let names = config
+            .repositories()
+            .iter()
+            .map(|repo| repo.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["personal", "company"]);
+        assert_eq!(config.get_repo(None).unwrap().name, "personal");
+        assert_eq!(config.get_repo(Some("company")).unwrap().name, "company");
+        assert!(config.get_repo(Some("missing")).is_err());
+    }
 }
@@ -1,5 +1,8 @@
+mod entry;
 mod ldfm_config;
 mod repo_config;
 
-pub use ldfm_config::LdfmConfig;
-pub use repo_config::RepoConfig;
+pub use entry::{BlockEntry, Entry, MergeEntry, MergeFormat, PartialEntry, PatternEntry};
+pub use ldfm_config::{DEFAULT_REPO_NAME, LdfmConfig, Repository, UnmanagedConfig};
+pub(crate) use repo_config::PRESERVED_FILES;
+pub use repo_config::{HOSTS_DIR, RepoConfig, SecretsConfig};
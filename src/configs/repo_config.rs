@@ -1,21 +1,80 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
+use super::{BlockEntry, Entry, MergeEntry, PatternEntry};
+use crate::errors::{IoContext, LdfmError, Result};
+
+/// Directory of the repository with host-specific copies of entries.
+pub const HOSTS_DIR: &str = "hosts";
+
+/// Files in the repository root that belong to the repository itself
+/// and must survive the cleanup before syncing dotfiles.
+pub(crate) const PRESERVED_FILES: [&str; 5] = [
+    ".git",
+    "ldfm.toml",
+    ".gitignore",
+    ".gitattributes",
+    HOSTS_DIR,
+];
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct RepoConfig {
     pub root: Option<PathBuf>,
-    pub files: HashMap<String, String>,
+    pub files: HashMap<String, Entry>,
+    /// Patterns of files inside tracked directories that are never committed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "SecretsConfig::is_default")]
+    pub secrets: SecretsConfig,
+}
+
+/// Settings of the secret scanner run before committing.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    /// Patterns of files in the repository that may contain secrets,
+    /// in the same syntax as `exclude`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+}
+
+impl SecretsConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 impl RepoConfig {
-    fn format_full_path(&self, path: &PathBuf) -> anyhow::Result<PathBuf> {
-        let mut file_path = std::path::absolute(path)?;
+    fn format_full_path(&self, path: &PathBuf) -> Result<PathBuf> {
+        let mut file_path = std::path::absolute(path).with_path("resolve", path)?;
         file_path = file_path.canonicalize().unwrap_or(file_path);
-        let home_dir = std::env::home_dir().ok_or(anyhow::anyhow!("Cannot get home directory"))?;
-        let relative_to_home = pathdiff::diff_paths(file_path, home_dir).ok_or(anyhow::anyhow!(
-            "Cannot calculate path relative to home directory."
+        Self::relative_to_home(file_path)
+    }
+
+    /// Like [`Self::format_full_path`], but a symlink is kept at its own path
+    /// instead of the path it points to.
+    fn format_link_path(&self, path: &PathBuf) -> Result<PathBuf> {
+        let file_path = std::path::absolute(path).with_path("resolve", path)?;
+        let file_path = match (file_path.parent(), file_path.file_name()) {
+            (Some(parent), Some(name)) => parent
+                .canonicalize()
+                .map_or(file_path.clone(), |parent| parent.join(name)),
+            _ => file_path,
+        };
+        Self::relative_to_home(file_path)
+    }
+
+    fn relative_to_home(file_path: PathBuf) -> Result<PathBuf> {
+        let home_dir = crate::utils::home_dir().ok_or(LdfmError::Validation(
+            "Cannot get home directory. Set HOME or LDFM_HOME.".to_string(),
         ))?;
+        let relative_to_home = pathdiff::diff_paths(file_path, home_dir).ok_or(
+            LdfmError::Validation("Cannot calculate path relative to home directory.".to_string()),
+        )?;
         Ok(PathBuf::from("~").join(relative_to_home))
     }
 
@@ -27,71 +86,267 @@ impl RepoConfig {
         }
     }
 
-    pub fn track_file(&mut self, target: &PathBuf) -> anyhow::Result<()> {
-        let filename = target
+    /// Path of the host-specific copy of the entry, relative to the repository.
+    pub fn overlay_path(&self, key: &str, host: &str) -> PathBuf {
+        Path::new(HOSTS_DIR).join(host).join(key)
+    }
+
+    /// Path of the entry's copy used on this machine, relative to the repository:
+    /// the copy of this host if there is one, otherwise the shared copy.
+    pub fn host_path(&self, repo_root: &Path, key: &str) -> PathBuf {
+        if let Some(host) = crate::utils::hostname() {
+            let overlay = self.overlay_path(key, &host);
+            if repo_root.join(&overlay).exists() {
+                return overlay;
+            }
+        }
+        self.get_local_path(key)
+    }
+
+    /// Path of the live file or directory of the entry.
+    ///
+    /// For patterns it's the directory the pattern is expanded in.
+    pub fn live_path(&self, key: &str) -> Result<PathBuf> {
+        let entry = self.files.get(key).ok_or(LdfmError::Validation(format!(
+            "Entry {} is not tracked",
+            key
+        )))?;
+        if let Entry::Pattern(pattern) = entry {
+            return pattern.base();
+        }
+        crate::utils::expand_tilde(entry.path()).ok_or(LdfmError::Validation(format!(
+            "Cannot expand tilde in {}",
+            entry.path()
+        )))
+    }
+
+    /// Track a file in the repository configuration.
+    ///
+    /// Returns the key of the entry. Files with the same name get
+    /// a numeric prefix to keep keys unique.
+    pub fn track_file(&mut self, target: &PathBuf) -> Result<String> {
+        let filename = Self::file_name(target)?;
+        let entry = Entry::Path(self.format_full_path(target)?.display().to_string());
+        Ok(self.insert_entry(filename, entry))
+    }
+
+    /// Track a file given by its path relative to the home directory.
+    ///
+    /// Unlike [`Self::track_file`], the path is recorded as given without resolving
+    /// symlinks, e.g. the ones GNU Stow creates in the home directory.
+    pub fn track_home_file(&mut self, relative: &Path) -> Result<String> {
+        let filename = Self::file_name(relative)?;
+        let entry = Entry::Path(Path::new("~").join(relative).display().to_string());
+        Ok(self.insert_entry(filename, entry))
+    }
+
+    /// Track a block of a file in the repository configuration.
+    ///
+    /// The key of the entry is the file name followed by the block name.
+    pub fn track_block(&mut self, target: &PathBuf, block: &str, comment: &str) -> Result<String> {
+        let name = format!("{}.{}", Self::file_name(target)?, block);
+        let entry = Entry::Block(BlockEntry {
+            path: self.format_full_path(target)?.display().to_string(),
+            block: block.to_string(),
+            comment: comment.to_string(),
+        });
+        Ok(self.insert_entry(name, entry))
+    }
+
+    /// Track keys of a structured config file in the repository configuration.
+    ///
+    /// If the file is already tracked this way, the keys are added to the managed ones.
+    pub fn track_merge(&mut self, target: &PathBuf, keys: &[String]) -> Result<String> {
+        let path = self.format_full_path(target)?.display().to_string();
+        for (key, value) in self.files.iter_mut() {
+            if let Entry::Merge(merge) = value
+                && merge.path == path
+            {
+                for managed in keys {
+                    if !merge.keys.contains(managed) {
+                        merge.keys.push(managed.clone());
+                    }
+                }
+                return Ok(key.clone());
+            }
+        }
+        let entry = MergeEntry {
+            path,
+            keys: keys.to_vec(),
+            format: None,
+        };
+        // Fail early if the format of the file can't be detected.
+        entry.format()?;
+        Ok(self.insert_entry(Self::file_name(target)?, Entry::Merge(entry)))
+    }
+
+    /// Track files matching a glob pattern in the repository configuration.
+    ///
+    /// The key of the entry is the name of the directory the pattern is expanded in.
+    pub fn track_pattern(&mut self, target: &PathBuf) -> Result<String> {
+        let entry = PatternEntry {
+            pattern: self.format_full_path(target)?.display().to_string(),
+        };
+        entry.matcher()?;
+        let name = Self::file_name(&entry.base()?)?;
+        Ok(self.insert_entry(name, Entry::Pattern(entry)))
+    }
+
+    fn file_name(target: &Path) -> Result<String> {
+        Ok(target
             .file_name()
-            .ok_or(anyhow::anyhow!("Cannot get filename from given path"))?
+            .ok_or(LdfmError::Validation(
+                "Cannot get filename from given path".to_string(),
+            ))?
             .to_string_lossy()
-            .to_string();
-        let dotfile_path = self.format_full_path(target)?;
+            .to_string())
+    }
+
+    /// Whether the key can't be used for an entry.
+    ///
+    /// Keys are names of files in the repository, so they must be plain names
+    /// that don't clash with files of the repository itself.
+    pub fn is_reserved_key(key: &str) -> bool {
+        key.is_empty()
+            || key == "."
+            || key == ".."
+            || key.contains(['/', '\\'])
+            || PRESERVED_FILES.contains(&key)
+    }
+
+    fn insert_entry(&mut self, name: String, entry: Entry) -> String {
         for (key, value) in self.files.iter() {
-            if value == &dotfile_path.display().to_string() {
+            if value.same_target(&entry) {
                 tracing::info!(
                     "File {} is already tracked with the same path: {}",
                     key,
-                    value
+                    value.path()
                 );
-                return Ok(());
+                return key.clone();
             }
         }
-        let value = self
-            .files
-            .entry(filename.clone())
-            .or_insert_with(|| dotfile_path.display().to_string());
-        if value == &dotfile_path.display().to_string() {
-            return Ok(());
+        if Self::is_reserved_key(&name) {
+            tracing::info!(
+                "{} is a file of the repository itself, using a prefixed key",
+                name
+            );
+        } else {
+            let Some(value) = self.files.get(&name) else {
+                self.files.insert(name.clone(), entry);
+                return name;
+            };
+            tracing::warn!(
+                "File {} is already tracked with a different path: {}",
+                name,
+                value.path()
+            );
         }
-        tracing::warn!(
-            "File {} is already tracked with a different path: {}",
-            filename,
-            value
-        );
         let mut has_key = true;
         let mut prefix = 0;
+        let mut key = String::new();
         while has_key {
-            let new_key = format!("{}-{}", prefix, filename);
-            if self.files.contains_key(&new_key) {
+            key = format!("{}-{}", prefix, name);
+            if self.files.contains_key(&key) {
                 prefix += 1;
             } else {
                 has_key = false;
-                self.files
-                    .insert(new_key, dotfile_path.display().to_string());
             }
         }
+        self.files.insert(key.clone(), entry);
+        key
+    }
+
+    /// Find the key under which the given path is tracked.
+    ///
+    /// With `block` set, looks for the block of the file instead of the whole file.
+    ///
+    /// A symlink is also found at its own path, e.g. when it was replaced with a link
+    /// into the repository after being tracked.
+    pub fn find_key(&self, target: &PathBuf, block: Option<&str>) -> Result<Option<String>> {
+        let find = |dotfile_path: PathBuf| {
+            let dotfile_path = dotfile_path.display().to_string();
+            self.files
+                .iter()
+                .find(|(_, value)| value.path() == dotfile_path && value.block() == block)
+                .map(|(key, _)| key.clone())
+        };
+        let found = find(self.format_full_path(target)?);
+        if found.is_none() && target.is_symlink() {
+            return Ok(find(self.format_link_path(target)?));
+        }
+        Ok(found)
+    }
 
-        Ok(())
+    /// Check whether any entry tracks the path, as a whole or partially.
+    pub fn is_tracked(&self, target: &PathBuf) -> Result<bool> {
+        let dotfile_path = self.format_full_path(target)?.display().to_string();
+        Ok(self
+            .files
+            .values()
+            .any(|value| value.path() == dotfile_path))
+    }
+
+    /// Check whether any entry tracks the path or something inside it.
+    pub fn tracks_within(&self, target: &PathBuf) -> Result<bool> {
+        let dir = self.format_full_path(target)?.display().to_string();
+        Ok(self
+            .files
+            .values()
+            .any(|value| moved_path(value.path(), &dir, "").is_some()))
+    }
+
+    /// Point entries tracking `from`, or anything inside it, to `to`.
+    ///
+    /// Returns keys of the changed entries, sorted.
+    pub fn move_target(&mut self, from: &PathBuf, to: &PathBuf) -> Result<Vec<String>> {
+        let from = self.format_full_path(from)?.display().to_string();
+        let to = self.format_full_path(to)?.display().to_string();
+        let mut keys = Vec::new();
+        for (key, value) in self.files.iter_mut() {
+            let path = match value {
+                Entry::Path(path) => path,
+                Entry::Block(block) => &mut block.path,
+                Entry::Merge(merge) => &mut merge.path,
+                Entry::Pattern(pattern) => &mut pattern.pattern,
+            };
+            let Some(moved) = moved_path(path, &from, &to) else {
+                continue;
+            };
+            *path = moved;
+            keys.push(key.clone());
+            if let Entry::Merge(merge) = value {
+                // The new extension may not be a known format.
+                merge.format()?;
+            }
+        }
+        keys.sort();
+        Ok(keys)
     }
 
     /// Untrack a file from the repository configuration.
     ///
     /// Target is the path to the file to untrack.
     /// Returns the key of the file if it was successfully untracked, or None if it was not tracked.
-    pub fn untrack_file(&mut self, target: &PathBuf) -> anyhow::Result<Option<String>> {
-        let dotfile_path = self.format_full_path(target)?.display().to_string();
-        let mut found_key = None;
-        for (key, value) in self.files.iter() {
-            if value == &dotfile_path {
-                found_key = Some(key.clone());
-            }
+    pub fn untrack_file(
+        &mut self,
+        target: &PathBuf,
+        block: Option<&str>,
+    ) -> Result<Option<String>> {
+        let found_key = self.find_key(target, block)?;
+        if let Some(key) = &found_key {
+            self.files.remove(key);
         }
-        if let Some(key) = found_key {
-            self.files.remove(&key);
-            return Ok(Some(key.to_string()));
-        }
-        Ok(None)
+        Ok(found_key)
     }
 }
 
+/// Path of an entry after moving `from` to `to`,
+/// or `None` if the path is neither `from` nor inside it.
+fn moved_path(path: &str, from: &str, to: &str) -> Option<String> {
+    let rest = path.strip_prefix(from)?;
+    (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", to, rest))
+}
+
 #[cfg(test)]
 mod tests {
     use super::RepoConfig;
@@ -99,7 +354,7 @@ mod tests {
     #[test]
     fn track_file() {
         let mut conf = RepoConfig::default();
-        let target = std::env::home_dir().unwrap().join(".config/meme.txt");
+        let target = crate::utils::home_dir().unwrap().join(".config/meme.txt");
         conf.track_file(&target).unwrap();
         assert!(conf.files.contains_key("meme.txt"));
         assert_eq!(conf.files.get("meme.txt").unwrap(), "~/.config/meme.txt");
@@ -108,7 +363,7 @@ mod tests {
     #[test]
     fn track_file_twice() {
         let mut conf = RepoConfig::default();
-        let target = std::env::home_dir().unwrap().join(".config/meme.txt");
+        let target = crate::utils::home_dir().unwrap().join(".config/meme.txt");
         conf.track_file(&target).unwrap();
         conf.track_file(&target).unwrap();
         assert!(conf.files.len() == 1);
@@ -119,8 +374,12 @@ mod tests {
     #[test]
     fn track_file_same_name() {
         let mut conf = RepoConfig::default();
-        let target = std::env::home_dir().unwrap().join(".config/hehe/meme.txt");
-        let target2 = std::env::home_dir().unwrap().join(".config/ohoh/meme.txt");
+        let target = crate::utils::home_dir()
+            .unwrap()
+            .join(".config/hehe/meme.txt");
+        let target2 = crate::utils::home_dir()
+            .unwrap()
+            .join(".config/ohoh/meme.txt");
         conf.track_file(&target).unwrap();
         conf.track_file(&target2).unwrap();
         assert!(conf.files.len() == 2);
@@ -139,9 +398,58 @@ mod tests {
     #[test]
     fn untrack_file() {
         let mut conf = RepoConfig::default();
-        let target = std::env::home_dir().unwrap().join(".config/meme.txt");
+        let target = crate::utils::home_dir().unwrap().join(".config/meme.txt");
         conf.track_file(&target).unwrap();
-        conf.untrack_file(&target).unwrap();
+        conf.untrack_file(&target, None).unwrap();
         assert_eq!(conf.files.len(), 0);
     }
+
+    #[test]
+    fn move_target_moves_nested_entries() {
+        let mut conf = RepoConfig::default();
+        let home = crate::utils::home_dir().unwrap();
+        conf.track_file(&home.join(".config/ldfm-test-app"))
+            .unwrap();
+        conf.track_file(&home.join(".config/ldfm-test-app/lua"))
+            .unwrap();
+        conf.track_pattern(&home.join(".config/ldfm-test-app/*.toml"))
+            .unwrap();
+        conf.track_file(&home.join(".config/ldfm-test-app-2"))
+            .unwrap();
+        let keys = conf
+            .move_target(
+                &home.join(".config/ldfm-test-app"),
+                &home.join(".config/moved"),
+            )
+            .unwrap();
+        assert_eq!(keys, ["0-ldfm-test-app", "ldfm-test-app", "lua"]);
+        for (key, path) in [
+            ("ldfm-test-app", "~/.config/moved"),
+            ("lua", "~/.config/moved/lua"),
+            ("0-ldfm-test-app", "~/.config/moved/*.toml"),
+            ("ldfm-test-app-2", "~/.config/ldfm-test-app-2"),
+        ] {
+            assert_eq!(conf.files[key].path(), path, "{key}");
+        }
+    }
+
+    #[test]
+    fn track_block() {
+        let mut conf = RepoConfig::default();
+        let target = crate::utils::home_dir().unwrap().join(".bashrc");
+        conf.track_file(&target).unwrap();
+        let key = conf.track_block(&target, "aliases", "#").unwrap();
+        assert_eq!(key, ".bashrc.aliases");
+        assert_eq!(conf.files.len(), 2);
+        assert_eq!(
+            conf.find_key(&target, None).unwrap().as_deref(),
+            Some(".bashrc")
+        );
+        assert_eq!(
+            conf.find_key(&target, Some("aliases")).unwrap().as_deref(),
+            Some(".bashrc.aliases")
+        );
+        conf.untrack_file(&target, Some("aliases")).unwrap();
+        assert_eq!(conf.files.len(), 1);
+    }
 }
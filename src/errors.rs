@@ -0,0 +1,167 @@
+//! Errors reported by ldfm.
+//!
+//! Every error maps to a stable process exit code, so scripts wrapping ldfm
+//! can tell what went wrong:
+//!
+//! | Code | Meaning                                          |
+//! |------|--------------------------------------------------|
+//! | 0    | Success                                          |
+//! | 1    | Unexpected error                                 |
+//! | 2    | Invalid command line arguments                   |
+//! | 3    | ldfm or the repository is not initialized        |
+//! | 4    | A config file cannot be parsed                   |
+//! | 5    | A git command failed                             |
+//! | 6    | Conflict with existing files or configuration    |
+//! | 7    | Invalid input                                    |
+//! | 8    | Filesystem error                                 |
+//! | 9    | Possible secrets found in files to commit        |
+//!
+//! When several entries fail, the exit code of the first failure is used.
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use crate::secrets::SecretFinding;
+
+pub type Result<T> = std::result::Result<T, LdfmError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LdfmError {
+    #[error("Config file not found at {}", path.display())]
+    ConfigMissing { path: PathBuf },
+    #[error("Cannot parse config file {}: {source}", path.display())]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("`git {args}` failed with {status}{}", format_stderr(stderr))]
+    Git {
+        args: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Validation(String),
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Possible secrets found in files to commit:{}", format_findings(.0))]
+    Secrets(Vec<SecretFinding>),
+    #[error("Some entries failed:{}", format_failures(.0))]
+    Failed(Vec<EntryFailure>),
+}
+
+/// An error that stopped a single entry from being applied or committed.
+#[derive(Debug)]
+pub struct EntryFailure {
+    pub repo: String,
+    pub key: String,
+    pub error: LdfmError,
+}
+
+impl std::fmt::Display for EntryFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.repo, self.key, self.error)
+    }
+}
+
+fn format_failures(failures: &[EntryFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("\n  {}", failure))
+        .collect()
+}
+
+fn format_findings(findings: &[SecretFinding]) -> String {
+    findings
+        .iter()
+        .map(|finding| format!("\n  {}", finding))
+        .collect()
+}
+
+fn format_stderr(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{}", stderr)
+    }
+}
+
+impl LdfmError {
+    pub fn io(context: impl Into<String>, source: impl Into<std::io::Error>) -> Self {
+        Self::Io {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Process exit code for the error. See the module docs for the full list.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ConfigMissing { .. } => 3,
+            Self::ConfigParse { .. } => 4,
+            Self::Git { .. } => 5,
+            Self::Conflict(_) => 6,
+            Self::Validation(_) => 7,
+            Self::Io { .. } => 8,
+            Self::Secrets(_) => 9,
+            Self::Failed(failures) => failures
+                .first()
+                .map_or(1, |failure| failure.error.exit_code()),
+        }
+    }
+
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::ConfigMissing { .. } => Some(
+                "Run `ldfm init` first, or point `--config`/`--repo` to an existing configuration."
+                    .to_string(),
+            ),
+            Self::ConfigParse { path, .. } => Some(format!(
+                "Fix the syntax error in {} or restore it from the repository history.",
+                path.display()
+            )),
+            Self::Git { stderr, .. } => {
+                if stderr.contains("Please tell me who you are") {
+                    Some("Configure git `user.name` and `user.email` and try again.".to_string())
+                } else if stderr.contains("non-fast-forward") || stderr.contains("fetch first") {
+                    Some("Pull remote changes with `ldfm apply` before pushing.".to_string())
+                } else if stderr.contains("Could not read from remote repository") {
+                    Some("Check the remote URL and your access to it.".to_string())
+                } else {
+                    None
+                }
+            }
+            Self::Io { source, .. } if source.kind() == std::io::ErrorKind::PermissionDenied => {
+                Some("Check permissions of the file and its parent directories.".to_string())
+            }
+            Self::Secrets(_) => Some(
+                "Remove the secrets, exclude the files or add them to `secrets.allow` in ldfm.toml. \
+                 Single lines can be allowed with an `ldfm:allow-secret` comment."
+                    .to_string(),
+            ),
+            Self::Failed(failures) => failures.first().and_then(|failure| failure.error.hint()),
+            Self::Conflict(_) | Self::Validation(_) | Self::Io { .. } => None,
+        }
+    }
+}
+
+/// Attach the failed operation and the path to IO errors.
+pub trait IoContext<T> {
+    fn with_path(self, action: &str, path: &Path) -> Result<T>;
+}
+
+impl<T, E: Into<std::io::Error>> IoContext<T> for std::result::Result<T, E> {
+    fn with_path(self, action: &str, path: &Path) -> Result<T> {
+        self.map_err(|err| LdfmError::io(format!("Cannot {} {}", action, path.display()), err))
+    }
+}
@@ -0,0 +1,85 @@
+//! Patterns of files inside tracked directories that ldfm ignores.
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::errors::{LdfmError, Result};
+
+/// Compiled `exclude` patterns of a repository config.
+///
+/// Patterns without a slash match the name of any file or directory,
+/// like in `.gitignore`. Patterns with a slash match the whole path
+/// relative to the tracked directory.
+#[derive(Debug, Clone)]
+pub struct Excludes {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = Glob::new(pattern.trim_start_matches('/')).map_err(|err| {
+                LdfmError::Validation(format!("Invalid exclude pattern {}: {}", pattern, err))
+            })?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        let build = |builder: GlobSetBuilder| {
+            builder
+                .build()
+                .map_err(|err| LdfmError::Validation(format!("Invalid exclude patterns: {}", err)))
+        };
+        Ok(Self {
+            names: build(names)?,
+            paths: build(paths)?,
+        })
+    }
+
+    /// Check the path relative to the tracked directory.
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        if self.paths.is_match(relative) {
+            return true;
+        }
+        let mut prefix = std::path::PathBuf::new();
+        for component in relative.components() {
+            prefix.push(component);
+            if self.names.is_match(component.as_os_str()) || self.paths.is_match(&prefix) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Excludes;
+
+    #[test]
+    fn names_and_paths() {
+        let excludes = Excludes::new(&[
+            "*.log".to_string(),
+            "node_modules".to_string(),
+            "/plugins/cache".to_string(),
+        ])
+        .unwrap();
+        assert!(excludes.is_excluded(Path::new("debug.log")));
+        assert!(excludes.is_excluded(Path::new("nested/dir/debug.log")));
+        assert!(excludes.is_excluded(Path::new("a/node_modules/pkg/index.js")));
+        assert!(excludes.is_excluded(Path::new("plugins/cache/file")));
+        assert!(!excludes.is_excluded(Path::new("other/plugins/cache")));
+        assert!(!excludes.is_excluded(Path::new("init.lua")));
+        assert!(!excludes.is_excluded(Path::new("")));
+    }
+}
@@ -0,0 +1,36 @@
+//! Linux Dotfiles Manager.
+//!
+//! ldfm copies tracked dotfiles into git repositories and applies them back
+//! to other machines. This crate exposes the same operations as the `ldfm`
+//! CLI, returning typed results instead of printing them.
+//!
+//! ```no_run
+//! use ldfm::{Ldfm, configs::LdfmConfig};
+//!
+//! let config = LdfmConfig::load("/home/user/.config/ldfm/config.toml".as_ref())?;
+//! let ldfm = Ldfm::open(config)?;
+//! ldfm.track("~/.bashrc".as_ref())?;
+//! let plan = ldfm.plan_apply()?;
+//! ldfm.apply_plan(&plan)?;
+//! ldfm.commit(true)?;
+//! # Ok::<(), ldfm::LdfmError>(())
+//! ```
+pub mod configs;
+pub mod errors;
+mod excludes;
+mod manager;
+mod merge;
+pub mod progress;
+pub mod report;
+mod secrets;
+mod utils;
+
+pub use errors::{LdfmError, Result};
+pub use manager::{
+    AppliedCommit, ApplyAction, ApplyConflict, ApplyPlan, ApplyReport, CommitReport, EditTarget,
+    ExportFormat, ExportReport, HistoryEntry, ImportReport, ImportSource, InitOptions, Ldfm,
+    MoveOutcome, OverlayOutcome, RenameOutcome, RollbackReport, TrackOutcome, UnmanagedEntry,
+    UntrackOptions, UntrackOutcome, WatchTarget, WrittenFile,
+};
+pub use secrets::SecretFinding;
+pub use utils::{expand_tilde, home_dir};
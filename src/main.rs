@@ -1,72 +1,193 @@
 use clap::{CommandFactory, Parser};
 
-use crate::{cli::Cli, configs::LdfmConfig};
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use ldfm::{
+    InitOptions, Ldfm, LdfmError, UntrackOptions, configs::LdfmConfig, errors::IoContext,
+    expand_tilde,
+};
+
+use crate::cli::Cli;
 
 mod cli;
 mod cmds;
-mod configs;
-mod utils;
+mod completions;
+mod output;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
     let args = Cli::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            let Some(err) = err.downcast_ref::<LdfmError>() else {
+                return ExitCode::FAILURE;
+            };
+            if let Some(hint) = err.hint() {
+                eprintln!("Hint: {}", hint);
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run(args: Cli) -> anyhow::Result<()> {
     tracing_subscriber::fmt()
         .with_level(true)
-        .with_writer(std::io::stderr)
+        .with_writer(output::log_writer)
         .with_max_level(args.log_level)
         .init();
-    let Some(config_dir) = dirs::config_dir().map(|p| p.join("ldfm")) else {
-        anyhow::bail!("Could not find user config directory.");
+    let config_file = match args.config {
+        Some(config_file) => config_file,
+        None => {
+            let Some(config_dir) = dirs::config_dir().map(|p| p.join("ldfm")) else {
+                return Err(LdfmError::Validation(
+                    "Could not find user config directory. Use `--config` to set the config path."
+                        .to_string(),
+                )
+                .into());
+            };
+            config_dir.join("config.toml")
+        }
     };
-    std::fs::create_dir_all(&config_dir)?;
-    let config_file = config_dir.join("config.toml");
-    let config = if config_file.exists() {
-        let contents = std::fs::read_to_string(&config_file)?;
-        let config: LdfmConfig = toml::from_str(&contents)?;
-        Some(config)
+    if let Some(config_dir) = config_file.parent() {
+        std::fs::create_dir_all(config_dir).with_path("create", config_dir)?;
+    }
+    let mut config = if config_file.exists() {
+        Some(LdfmConfig::load(&config_file)?)
     } else {
         None
     };
-    let initialization_error_message = "ldfm was not initialized. Please run `ldfm init` first.";
+    // Commands other than `init` operate on the repository selected with `--repo`.
+    let mut repo = None;
+    if let Some(selector) = &args.repo
+        && !matches!(args.command, cli::Command::Init { .. })
+    {
+        let (selected_config, name) = LdfmConfig::select_repo(config, selector)?;
+        config = Some(selected_config);
+        repo = Some(name);
+    }
+    let open = || -> anyhow::Result<Ldfm> {
+        let Some(mut config) = config.clone() else {
+            return Err(LdfmError::ConfigMissing {
+                path: config_file.clone(),
+            }
+            .into());
+        };
+        config.jobs = args.jobs.or(config.jobs);
+        Ok(Ldfm::open(config)?.select(repo.clone())?)
+    };
 
     match args.command {
         cli::Command::Init {
+            name,
+            priority,
             local_path,
             git_repo,
+            new,
+            branch,
+            force,
         } => {
-            let local_path = simple_expand_tilde::expand_tilde(&local_path)
-                .ok_or(anyhow::anyhow!("Cannot expand tilde from path"))?;
-            cmds::init::run(config_file, local_path, git_repo)?;
+            let local_path = local_path
+                .or(args.repo.map(PathBuf::from))
+                .unwrap_or(PathBuf::from("~/.config/dotfiles"));
+            let local_path = expand_tilde(&local_path).ok_or(LdfmError::Validation(
+                "Cannot expand tilde from path".to_string(),
+            ))?;
+            cmds::init::run(
+                &config_file,
+                config,
+                InitOptions {
+                    name,
+                    priority,
+                    local_path,
+                    git_repo,
+                    new,
+                    branch,
+                    force,
+                },
+            )?;
         }
-        cli::Command::Commit { push } => {
-            let Some(config) = config else {
-                anyhow::bail!(initialization_error_message)
-            };
-            cmds::track::sync(config, push)?;
+        cli::Command::Commit { push, host_local } => {
+            cmds::track::sync(&open()?, push, &host_local, args.format)?
         }
-        cli::Command::Track { path } => {
-            let Some(config) = config else {
-                anyhow::bail!(initialization_error_message)
-            };
-            cmds::track::add(config, path)?;
+        cli::Command::Track {
+            path,
+            block,
+            comment,
+            merge_keys,
+        } => cmds::track::add(&open()?, path, block, &comment, &merge_keys)?,
+        cli::Command::Untrack {
+            path,
+            block,
+            purge,
+            restore,
+        } => cmds::track::remove(
+            &open()?,
+            path,
+            UntrackOptions {
+                block,
+                keep_copy: false,
+                commit: purge,
+                restore,
+            },
+        )?,
+        cli::Command::Forget { path, block } => cmds::track::remove(
+            &open()?,
+            path,
+            UntrackOptions {
+                block,
+                keep_copy: true,
+                ..Default::default()
+            },
+        )?,
+        cli::Command::Mv { from, to } => cmds::track::move_target(&open()?, from, to)?,
+        cli::Command::RenameKey { from, to } => cmds::track::rename_key(&open()?, &from, &to)?,
+        cli::Command::Edit {
+            path,
+            block,
+            commit,
+        } => cmds::edit::run(&open()?, path, block, commit)?,
+        cli::Command::Export { output, kind } => {
+            cmds::export::run(&open()?, output, kind.map(Into::into))?
         }
-        cli::Command::Untrack { path } => {
-            let Some(config) = config else {
-                anyhow::bail!(initialization_error_message)
-            };
-            cmds::track::remove(config, path)?;
-        }
-        cli::Command::List => {
-            let Some(config) = config else {
-                anyhow::bail!(initialization_error_message)
-            };
-            cmds::track::list(config)?;
+        cli::Command::Import { from, path } => cmds::import::run(&open()?, from.into(), path)?,
+        cli::Command::List => cmds::track::list(&open()?, args.format)?,
+        cli::Command::Unmanaged {
+            roots,
+            depth,
+            ignore,
+            interactive,
+        } => {
+            let ldfm = open()?;
+            let mut options = ldfm.config().unmanaged.clone();
+            if !roots.is_empty() {
+                options.roots = roots;
+            }
+            if let Some(depth) = depth {
+                options.depth = depth.into();
+            }
+            options.ignore.extend(ignore);
+            cmds::unmanaged::run(&ldfm, &options, interactive, args.format)?
         }
-        cli::Command::Apply { no_pull } => {
-            let Some(config) = config else {
-                anyhow::bail!(initialization_error_message)
-            };
-            cmds::apply::run(config, no_pull)?;
+        cli::Command::Status { rev } => cmds::status::run(open()?, rev, args.format)?,
+        cli::Command::Apply { no_pull, rev } => {
+            cmds::apply::run(open()?, no_pull, rev, args.format)?
         }
+        cli::Command::Overlay { command } => match command {
+            cli::OverlayCommand::Promote { path } => cmds::overlay::promote(&open()?, path)?,
+        },
+        cli::Command::History => cmds::history::list(&open()?, args.format)?,
+        cli::Command::Rollback { id } => cmds::history::rollback(&open()?, id)?,
+        cli::Command::Watch {
+            debounce,
+            push_interval,
+        } => cmds::watch::run(
+            &open()?,
+            Duration::from_secs(debounce),
+            push_interval.map(Duration::from_secs),
+        )?,
         cli::Command::Completions { shell } => {
             let mut cmd = Cli::command();
             clap_complete::generate(
@@ -0,0 +1,252 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::Entry,
+    errors::{EntryFailure, IoContext, Result},
+    progress::Progress,
+    report::{SyncOutcome, SyncStatus, same_contents},
+    utils::{git_pull, parallel_map, walk_dir},
+};
+
+use super::transaction::Transaction;
+
+/// Files to copy from repositories to this machine.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApplyPlan {
+    /// Copies in the order they must be applied: from the lowest priority
+    /// repository to the highest, so files of higher priority repositories
+    /// overwrite nested ones.
+    pub actions: Vec<ApplyAction>,
+    /// Targets tracked by several repositories.
+    pub conflicts: Vec<ApplyConflict>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyAction {
+    pub repo: String,
+    pub key: String,
+    /// Copy of the entry in the repository.
+    pub source: PathBuf,
+    /// Where the entry is applied to.
+    pub target: PathBuf,
+    /// Tracked entry as written in the repository config.
+    pub entry: Entry,
+}
+
+/// A target tracked by several repositories.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyConflict {
+    pub target: PathBuf,
+    /// Repository the target is applied from.
+    pub winner: String,
+    /// Repository whose copy of the target is skipped.
+    pub skipped: String,
+}
+
+/// Result of [`Ldfm::apply_plan`].
+#[derive(Debug, Default, Serialize)]
+pub struct ApplyReport {
+    /// Actions that were applied.
+    pub applied: Vec<ApplyAction>,
+    /// What happened to every entry, in the order of the plan.
+    pub entries: Vec<SyncOutcome>,
+    /// Entries that couldn't be staged. Nothing is applied if there are any.
+    #[serde(skip)]
+    pub failures: Vec<EntryFailure>,
+    /// Id of the history entry to roll the apply back with.
+    /// `None` if nothing was applied.
+    pub history_id: Option<u64>,
+}
+
+impl Ldfm {
+    /// Pull the latest changes of all repositories.
+    ///
+    /// Failed pulls are logged, but don't stop the process.
+    pub fn pull(&self) -> Result<()> {
+        for repo in self.repositories() {
+            tracing::info!(
+                "Pulling latest changes for {} repository from remote...",
+                repo.name
+            );
+            git_pull(&repo.local_path.display().to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Compute what `apply` would copy without touching any files.
+    pub fn plan_apply(&self) -> Result<ApplyPlan> {
+        let mut plan = ApplyPlan::default();
+        // Targets are claimed by repositories starting from the highest priority,
+        // so the first repository to claim a target owns it.
+        // Blocks are owned separately from the rest of the file.
+        let mut owners: HashMap<(PathBuf, Option<String>), &str> = HashMap::new();
+        let mut layers = Vec::new();
+        for repo in self.repositories() {
+            let repo_config = repo.get_repo_config()?;
+            let mut keys = repo_config.files.keys().collect::<Vec<_>>();
+            keys.sort();
+            let mut actions = Vec::new();
+            for key in keys {
+                let entry = &repo_config.files[key];
+                let Ok(target) = repo_config.live_path(key) else {
+                    continue;
+                };
+                let owned = (target.clone(), entry.block().map(str::to_string));
+                if let Some(owner) = owners.get(&owned) {
+                    plan.conflicts.push(ApplyConflict {
+                        target,
+                        winner: owner.to_string(),
+                        skipped: repo.name.clone(),
+                    });
+                    continue;
+                }
+                owners.insert(owned, &repo.name);
+                let source = repo
+                    .local_path
+                    .join(repo_config.host_path(&repo.local_path, key));
+                if !source.exists() {
+                    tracing::debug!("{} was never committed to {}", key, repo.name);
+                    continue;
+                }
+                actions.push(ApplyAction {
+                    repo: repo.name.clone(),
+                    key: key.clone(),
+                    source,
+                    target,
+                    entry: entry.clone(),
+                });
+            }
+            layers.push(actions);
+        }
+        plan.actions = layers.into_iter().rev().flatten().collect();
+        Ok(plan)
+    }
+
+    /// Copy files according to the plan.
+    ///
+    /// All files are staged next to their targets first and moved into place
+    /// together, so a failure leaves this machine as it was before the apply.
+    /// Overwritten files are backed up and the apply is recorded in the history,
+    /// so it can be undone with [`Ldfm::rollback`].
+    ///
+    /// Entries that can't be staged are collected in [`ApplyReport::failures`]
+    /// and nothing is applied in that case.
+    pub fn apply_plan(&self, plan: &ApplyPlan) -> Result<ApplyReport> {
+        let progress = self.progress();
+        progress.start(plan.actions.len());
+        let report = self.apply_actions(plan, progress);
+        progress.finish();
+        report
+    }
+
+    fn apply_actions(&self, plan: &ApplyPlan, progress: &dyn Progress) -> Result<ApplyReport> {
+        let mut transaction = Transaction::default();
+        let mut report = ApplyReport::default();
+        for action in &plan.actions {
+            progress.entry(&action.repo, &action.key);
+            let staged = stage_action(&mut transaction, action, progress, self.jobs());
+            progress.entry_done();
+            match staged {
+                Ok(outcome) => report.entries.push(outcome),
+                Err(error) => {
+                    report.entries.push(
+                        SyncOutcome::new(&action.repo, &action.key, SyncStatus::Failed)
+                            .with_reason(error.to_string()),
+                    );
+                    report.failures.push(EntryFailure {
+                        repo: action.repo.clone(),
+                        key: action.key.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        if !report.failures.is_empty() {
+            for outcome in &mut report.entries {
+                if outcome.status != SyncStatus::Failed {
+                    outcome.status = SyncStatus::Skipped;
+                    outcome.reason = Some("not applied because other entries failed".to_string());
+                }
+            }
+            return Ok(report);
+        }
+        if transaction.is_empty() {
+            return Ok(report);
+        }
+        let history_id = self.record_apply(&transaction.targets(), transaction.created_dirs())?;
+        if let Err(err) = transaction.commit() {
+            self.discard_apply(history_id)?;
+            return Err(err);
+        }
+        report.applied = plan.actions.clone();
+        report.history_id = Some(history_id);
+        Ok(report)
+    }
+}
+
+/// Stage files of a single action, skipping the ones that are already up to date.
+///
+/// Files of a directory are compared and copied on up to `jobs` threads.
+fn stage_action(
+    transaction: &mut Transaction,
+    action: &ApplyAction,
+    progress: &dyn Progress,
+    jobs: usize,
+) -> Result<SyncOutcome> {
+    let mut outcome = SyncOutcome::new(&action.repo, &action.key, SyncStatus::Unchanged);
+    let existed = action.target.exists();
+    let mut staged = false;
+    match action.entry.partial() {
+        Some(partial) => {
+            let contents =
+                std::fs::read_to_string(&action.source).with_path("read", &action.source)?;
+            let live_path = partial.live_path()?;
+            let live = transaction.read(&live_path)?;
+            let rendered = partial.render_live(&live, &contents)?;
+            if rendered != live || !live_path.exists() {
+                transaction.stage_contents(&live_path, &rendered)?;
+                outcome.bytes = rendered.len() as u64;
+                progress.copied(outcome.bytes);
+                staged = true;
+            }
+        }
+        None => {
+            let files = if action.source.is_dir() {
+                walk_dir(&action.source, true)?
+                    .into_iter()
+                    .map(|file| (action.source.join(&file), action.target.join(&file)))
+                    .collect()
+            } else {
+                vec![(action.source.clone(), action.target.clone())]
+            };
+            // Targets already staged by another entry are always staged again,
+            // the live file doesn't tell what they will contain.
+            let staged_targets = &*transaction;
+            let unchanged = parallel_map(&files, jobs, |(source, target)| {
+                Ok(!staged_targets.is_staged(target)
+                    && target.exists()
+                    && same_contents(source, target)?)
+            });
+            let mut changed = Vec::new();
+            for (file, unchanged) in files.into_iter().zip(unchanged) {
+                if !unchanged? {
+                    changed.push(file);
+                }
+            }
+            if !changed.is_empty() {
+                outcome.bytes =
+                    transaction.stage_copies(&changed, &|bytes| progress.copied(bytes), jobs)?;
+                staged = true;
+            }
+        }
+    }
+    outcome.status = match (staged, existed) {
+        (false, _) => SyncStatus::Unchanged,
+        (true, true) => SyncStatus::Updated,
+        (true, false) => SyncStatus::Created,
+    };
+    Ok(outcome)
+}
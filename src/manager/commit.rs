@@ -0,0 +1,536 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::{Entry, PRESERVED_FILES, RepoConfig, Repository},
+    errors::{EntryFailure, IoContext, LdfmError, Result},
+    excludes::Excludes,
+    report::{SyncOutcome, SyncStatus},
+    secrets::Scanner,
+    utils::{
+        copy_files, copy_tree, git, git_commit, git_commit_paths, git_push, git_stdout,
+        remove_path, run_git,
+    },
+};
+
+/// Result of committing a single repository.
+#[derive(Debug, Default, Serialize)]
+pub struct CommitReport {
+    pub repo: String,
+    /// Keys of entries copied to the repository.
+    pub copied: Vec<String>,
+    /// Keys of entries whose tracked paths don't exist.
+    pub missing: Vec<String>,
+    /// What happened to every entry, in the order they were processed.
+    pub entries: Vec<SyncOutcome>,
+    /// Entries that couldn't be copied.
+    /// Their copies are left as they were in the last commit.
+    #[serde(skip)]
+    pub failures: Vec<EntryFailure>,
+    /// Whether a new commit was created.
+    pub committed: bool,
+    /// Whether the repository was pushed.
+    pub pushed: bool,
+    /// Error that stopped the repository from being committed.
+    #[serde(skip)]
+    pub error: Option<LdfmError>,
+}
+
+impl Ldfm {
+    /// Copy tracked files into their repositories and commit each repository separately.
+    ///
+    /// Entries that can't be copied don't stop the commit,
+    /// they are collected in [`CommitReport::failures`]. Neither does a repository
+    /// that can't be committed stop the others, see [`CommitReport::error`].
+    pub fn commit(&self, push: bool) -> Result<Vec<CommitReport>> {
+        let mut entries = 0;
+        for repo in self.repositories() {
+            entries += repo.get_repo_config()?.files.len();
+        }
+        self.progress().start(entries);
+        let reports = self
+            .repositories()
+            .into_iter()
+            .map(|repo| {
+                tracing::info!("Syncing {} repository.", repo.name);
+                commit_repo(self, repo, push).unwrap_or_else(|error| CommitReport {
+                    repo: repo.name.clone(),
+                    error: Some(error),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        self.progress().finish();
+        Ok(reports)
+    }
+
+    /// Copy only the given entries into their repositories and commit them.
+    ///
+    /// Entries are given as repository names mapped to keys of changed entries.
+    /// Unlike [`Ldfm::commit`], the rest of the repository is left untouched:
+    /// only the copies of the entries and `ldfm.toml` are committed.
+    pub fn commit_entries(
+        &self,
+        entries: &BTreeMap<String, BTreeSet<String>>,
+        push: bool,
+    ) -> Result<Vec<CommitReport>> {
+        self.progress()
+            .start(entries.values().map(BTreeSet::len).sum());
+        let reports = self.commit_selected(entries, push);
+        self.progress().finish();
+        reports
+    }
+
+    fn commit_selected(
+        &self,
+        entries: &BTreeMap<String, BTreeSet<String>>,
+        push: bool,
+    ) -> Result<Vec<CommitReport>> {
+        let mut reports = Vec::new();
+        for repo in self.repositories() {
+            let Some(keys) = entries.get(&repo.name) else {
+                continue;
+            };
+            let repo_config = repo.get_repo_config()?;
+            let excludes = Excludes::new(&repo_config.exclude)?;
+            let mut report = CommitReport {
+                repo: repo.name.clone(),
+                ..Default::default()
+            };
+            // The entries need their config to be of any use elsewhere.
+            let mut paths = vec![PathBuf::from("ldfm.toml")];
+            for key in keys {
+                if !repo_config.files.contains_key(key) {
+                    continue;
+                }
+                self.progress().entry(&repo.name, key);
+                let host_path = repo_config.host_path(&repo.local_path, key);
+                paths.push(host_path.clone());
+                let target_path = repo.local_path.join(&host_path);
+                let copied = remove_path(&target_path).and_then(|()| {
+                    copy_entry(
+                        self,
+                        &repo_config,
+                        &excludes,
+                        key,
+                        &target_path,
+                        &mut report,
+                    )
+                });
+                record_copy(repo, key, &host_path, copied, &mut report)?;
+                self.progress().entry_done();
+            }
+            let message = format!("Dotfiles sync: {}.", report.copied.join(", "));
+            reports.push(finish_commit(
+                repo,
+                &repo_config,
+                &message,
+                push,
+                Some(&paths),
+                report,
+            )?);
+        }
+        Ok(reports)
+    }
+
+    /// Push all repositories.
+    pub fn push(&self) -> Result<()> {
+        for repo in self.repositories() {
+            tracing::info!("Pushing {} repository to remote.", repo.name);
+            git_push(&repo.local_path.display().to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn commit_repo(ldfm: &Ldfm, repo: &Repository, push: bool) -> Result<CommitReport> {
+    let mut report = CommitReport {
+        repo: repo.name.clone(),
+        ..Default::default()
+    };
+    let repo_config = repo.get_repo_config()?;
+    clean_repo(repo, &repo_config)?;
+    let excludes = Excludes::new(&repo_config.exclude)?;
+    let mut keys = repo_config.files.keys().collect::<Vec<_>>();
+    keys.sort();
+    let repo_path = repo.local_path.display().to_string();
+    for key in keys {
+        ldfm.progress().entry(&repo.name, key);
+        let host_path = repo_config.host_path(&repo.local_path, key);
+        let mut copy = || {
+            if host_path != repo_config.get_local_path(key) {
+                // The shared copy of a host-specific entry belongs to other machines.
+                let shared = repo_config.get_local_path(key);
+                if !restore_from_head(&repo_path, &shared)? {
+                    remove_path(&repo.local_path.join(&shared))?;
+                }
+                remove_path(&repo.local_path.join(&host_path))?;
+            }
+            let target_path = repo.local_path.join(&host_path);
+            copy_entry(
+                ldfm,
+                &repo_config,
+                &excludes,
+                key,
+                &target_path,
+                &mut report,
+            )
+        };
+        let copied = copy();
+        record_copy(repo, key, &host_path, copied, &mut report)?;
+        ldfm.progress().entry_done();
+    }
+    finish_commit(repo, &repo_config, "Dotfiles sync.", push, None, report)
+}
+
+/// Remove everything but repository's own files, so files
+/// that are no longer tracked disappear from the repository.
+fn clean_repo(repo: &Repository, repo_config: &RepoConfig) -> Result<()> {
+    let mut dotfiles_path = repo.local_path.clone();
+    if let Some(df_root) = &repo_config.root {
+        dotfiles_path = dotfiles_path.join(df_root);
+        if dotfiles_path.exists() {
+            std::fs::remove_dir_all(&dotfiles_path).with_path("remove", &dotfiles_path)?;
+        }
+        std::fs::create_dir_all(&dotfiles_path).with_path("create", &dotfiles_path)?;
+    } else {
+        dotfiles_path = repo.local_path.clone();
+        for entry in std::fs::read_dir(&dotfiles_path).with_path("read", &dotfiles_path)? {
+            let entry = entry.with_path("read", &dotfiles_path)?.path();
+            let Some(file_name) = entry.file_name() else {
+                continue;
+            };
+            if PRESERVED_FILES.contains(&file_name.to_string_lossy().as_ref()) {
+                continue;
+            }
+            if entry.is_dir() {
+                std::fs::remove_dir_all(&entry).with_path("remove", &entry)?;
+            } else {
+                std::fs::remove_file(&entry).with_path("remove", &entry)?;
+            }
+        }
+    }
+    let df_contents = fs_extra::dir::get_dir_content(&dotfiles_path).map_err(|err| {
+        LdfmError::io(
+            format!("Cannot read {}", dotfiles_path.display()),
+            std::io::Error::other(err),
+        )
+    })?;
+    // Clean up the dotfiles directory by removing files and directories
+    let preserved = PRESERVED_FILES.map(|name| repo.local_path.join(name).display().to_string());
+    df_contents
+        .files
+        .iter()
+        .filter(|filepath| {
+            // We filter out files that are in the .git directory or repository's own files.
+            !preserved
+                .iter()
+                .any(|preserved| filepath.starts_with(preserved.as_str()))
+        })
+        .for_each(|file| {
+            tracing::debug!("Removing file: {}", file);
+            fs_extra::remove_items(&[file]).ok();
+        });
+    df_contents
+        .directories
+        .iter()
+        .filter(|dir| {
+            // We filter out directories of the repository itself or the dotfiles directory iteslf.
+            !(preserved
+                .iter()
+                .any(|preserved| dir.starts_with(preserved.as_str()))
+                || dir == &&repo.local_path.display().to_string())
+        })
+        .for_each(|dir| {
+            tracing::info!("Removing directory: {}", dir);
+            fs_extra::remove_items(&[dir]).ok();
+        });
+    std::fs::create_dir_all(&dotfiles_path).with_path("create", &dotfiles_path)?;
+    Ok(())
+}
+
+/// Copy a single tracked entry to `target_path` inside the repository.
+///
+/// Returns the size of copied files, or `None` if the tracked path doesn't exist.
+pub(super) fn copy_entry(
+    ldfm: &Ldfm,
+    repo_config: &RepoConfig,
+    excludes: &Excludes,
+    key: &str,
+    target_path: &Path,
+    report: &mut CommitReport,
+) -> Result<Option<u64>> {
+    let progress = ldfm.progress();
+    if let Some(partial) = repo_config.files[key].partial() {
+        let Some(contents) = partial.read_live()? else {
+            tracing::warn!(
+                "Managed part of {} is not found in {}",
+                key,
+                partial.live_path()?.display()
+            );
+            report.missing.push(key.to_string());
+            return Ok(None);
+        };
+        if let Some(parent) = target_path.parent() {
+            std::fs::create_dir_all(parent).with_path("create", parent)?;
+        }
+        std::fs::write(target_path, &contents).with_path("write", target_path)?;
+        progress.copied(contents.len() as u64);
+        report.copied.push(key.to_string());
+        return Ok(Some(contents.len() as u64));
+    }
+    if let Entry::Pattern(pattern) = &repo_config.files[key] {
+        let base = pattern.base()?;
+        let matches = pattern.expand()?;
+        if matches.is_empty() {
+            tracing::warn!("No files match {}", pattern.pattern);
+            report.missing.push(key.to_string());
+            return Ok(None);
+        }
+        let mut files = Vec::new();
+        for file in matches {
+            let target = target_path.join(&file);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).with_path("create", parent)?;
+            }
+            files.push((base.join(&file), target));
+        }
+        let bytes = copy_files(&files, &|bytes| progress.copied(bytes), ldfm.jobs())?;
+        report.copied.push(key.to_string());
+        return Ok(Some(bytes));
+    }
+    let actual_path = repo_config.live_path(key)?;
+    if !actual_path.exists() {
+        tracing::warn!(
+            "File {} does not exist at the expected path: {}",
+            key,
+            actual_path.display()
+        );
+        report.missing.push(key.to_string());
+        return Ok(None);
+    }
+    tracing::debug!(
+        "Copying file from {} to {}",
+        actual_path.display(),
+        target_path.display()
+    );
+    let bytes = copy_tree(
+        &actual_path,
+        target_path,
+        &|relative| excludes.is_excluded(relative),
+        &|bytes| progress.copied(bytes),
+        ldfm.jobs(),
+    )?;
+    report.copied.push(key.to_string());
+    Ok(Some(bytes))
+}
+
+/// Add the outcome of copying the entry to the report.
+///
+/// The copy of a failed entry, or of one missing on this machine, is restored
+/// to its committed state, so the commit doesn't drop or half-update it.
+fn record_copy(
+    repo: &Repository,
+    key: &str,
+    host_path: &Path,
+    copied: Result<Option<u64>>,
+    report: &mut CommitReport,
+) -> Result<()> {
+    match copied {
+        Ok(Some(bytes)) => {
+            // Created and unchanged entries are told apart in `resolve_statuses`.
+            let mut outcome = SyncOutcome::new(&repo.name, key, SyncStatus::Updated);
+            outcome.bytes = bytes;
+            report.entries.push(outcome);
+        }
+        Ok(None) => {
+            restore_from_head(&repo.local_path.display().to_string(), host_path)?;
+            report.entries.push(
+                SyncOutcome::new(&repo.name, key, SyncStatus::Skipped)
+                    .with_reason("not found on this machine"),
+            );
+        }
+        Err(error) => {
+            remove_path(&repo.local_path.join(host_path))?;
+            restore_from_head(&repo.local_path.display().to_string(), host_path)?;
+            report.entries.push(
+                SyncOutcome::new(&repo.name, key, SyncStatus::Failed)
+                    .with_reason(error.to_string()),
+            );
+            report.failures.push(EntryFailure {
+                repo: repo.name.clone(),
+                key: key.to_string(),
+                error,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Compare copies of the entries with the last commit to tell
+/// created, updated and unchanged entries apart.
+///
+/// Entries with nothing git would commit, e.g. because `.gitignore` matches them,
+/// are skipped.
+fn resolve_statuses(
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    report: &mut CommitReport,
+) -> Result<()> {
+    let repo_path = repo.local_path.display().to_string();
+    let status = git_stdout([
+        "-C",
+        &repo_path,
+        "status",
+        "--porcelain",
+        "-z",
+        "--untracked-files=all",
+        "--ignored",
+    ])?;
+    let mut changed = Vec::new();
+    let mut ignored = Vec::new();
+    for line in status.split(|byte| *byte == 0) {
+        let Some(path) = line.get(3..) else {
+            continue;
+        };
+        let path = String::from_utf8_lossy(path).into_owned();
+        if line.starts_with(b"!! ") {
+            // Whole ignored directories end with a slash.
+            ignored.push(path.trim_end_matches('/').to_string());
+        } else {
+            changed.push(path);
+        }
+    }
+    // HEAD doesn't exist before the first commit.
+    let committed = git_stdout([
+        "-C",
+        &repo_path,
+        "ls-tree",
+        "-r",
+        "--name-only",
+        "-z",
+        "HEAD",
+    ])
+    .unwrap_or_default();
+    let committed = committed
+        .split(|byte| *byte == 0)
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>();
+    let is_within = |file: &str, dir: &str| {
+        file.strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    for outcome in &mut report.entries {
+        if outcome.status != SyncStatus::Updated {
+            continue;
+        }
+        let path = repo_config
+            .host_path(&repo.local_path, &outcome.key)
+            .display()
+            .to_string();
+        let is_ignored = ignored
+            .iter()
+            .any(|file| is_within(file, &path) || is_within(&path, file));
+        if !changed.iter().any(|file| is_within(file, &path)) {
+            if committed.iter().any(|file| is_within(file, &path)) {
+                outcome.status = SyncStatus::Unchanged;
+            } else {
+                outcome.status = SyncStatus::Skipped;
+                outcome.reason = Some(if is_ignored {
+                    "ignored by git".to_string()
+                } else {
+                    "no files to commit".to_string()
+                });
+                continue;
+            }
+        } else if !committed.iter().any(|file| is_within(file, &path)) {
+            outcome.status = SyncStatus::Created;
+        }
+        if is_ignored {
+            outcome.reason = Some("some files are ignored by git".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Check and commit the copied entries.
+///
+/// With `paths` set, only those paths relative to the repository are committed,
+/// otherwise all changes in it are.
+fn finish_commit(
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    message: &str,
+    push: bool,
+    paths: Option<&[PathBuf]>,
+    mut report: CommitReport,
+) -> Result<CommitReport> {
+    let repo_path = repo.local_path.display().to_string();
+    scan_secrets(repo, repo_config, &report)?;
+    resolve_statuses(repo, repo_config, &mut report)?;
+    report.committed = match paths {
+        Some(paths) => git_commit_paths(&repo_path, message, paths)?,
+        None => git_commit(&repo_path, message)?,
+    };
+    if push {
+        tracing::info!("Pushing changes to remote repository.");
+        git_push(&repo_path)?;
+        report.pushed = true;
+    }
+    Ok(report)
+}
+
+/// Check the copied entries for secrets before they are committed.
+///
+/// Files with findings are restored to their committed state,
+/// so secrets don't stay in the working tree of the repository.
+fn scan_secrets(repo: &Repository, repo_config: &RepoConfig, report: &CommitReport) -> Result<()> {
+    let scanner = Scanner::new(&repo_config.secrets.allow)?;
+    let mut findings = Vec::new();
+    let mut flagged = Vec::new();
+    for key in &report.copied {
+        let host_path = repo_config.host_path(&repo.local_path, key);
+        // Findings are reported relative to the directory the key is stored in,
+        // which is the dotfiles root for shared copies.
+        let base = host_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let path = repo.local_path.join(&host_path);
+        for finding in scanner.scan(&repo.name, &repo.local_path.join(&base), &path)? {
+            let relative = base.join(&finding.path);
+            if !flagged.contains(&relative) {
+                flagged.push(relative);
+            }
+            findings.push(finding);
+        }
+    }
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let repo_path = repo.local_path.display().to_string();
+    for path in flagged {
+        if !restore_from_head(&repo_path, &path)? {
+            remove_path(&repo.local_path.join(&path))?;
+        }
+    }
+    Err(LdfmError::Secrets(findings))
+}
+
+/// Restore the path relative to the repository to its committed state.
+///
+/// Returns `false` if the path isn't in the last commit.
+fn restore_from_head(repo_path: &str, path: &Path) -> Result<bool> {
+    let relative = path.display().to_string();
+    let spec = format!("HEAD:{}", relative);
+    if !run_git(["-C", repo_path, "cat-file", "-e", &spec])?
+        .status
+        .success()
+    {
+        return Ok(false);
+    }
+    git(["-C", repo_path, "checkout", "HEAD", "--", &relative])?;
+    Ok(true)
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    errors::{LdfmError, Result},
+    excludes::Excludes,
+    manager::{ApplyReport, CommitReport, commit::copy_entry, resolve_path},
+};
+
+/// Repository copy of a tracked entry, see [`Ldfm::edit_target`].
+#[derive(Debug, Clone, Serialize)]
+pub struct EditTarget {
+    pub repo: String,
+    pub key: String,
+    /// Copy of the entry in the repository to edit.
+    pub source: PathBuf,
+}
+
+impl Ldfm {
+    /// Find the repository copy of a tracked file, so it can be edited in place.
+    ///
+    /// With `block` set, the copy of the block of the file is returned.
+    /// If the entry was never committed, its copy is created from the file on this machine.
+    pub fn edit_target(&self, path: &Path, block: Option<&str>) -> Result<EditTarget> {
+        let target = resolve_path(path)?;
+        let not_tracked =
+            || LdfmError::Validation(format!("File {} is not tracked.", target.display()));
+        let repo = self.find_owner(&target, block)?.ok_or_else(not_tracked)?;
+        let repo_config = repo.get_repo_config()?;
+        let key = repo_config
+            .find_key(&target, block)?
+            .ok_or_else(not_tracked)?;
+        let source = repo
+            .local_path
+            .join(repo_config.host_path(&repo.local_path, &key));
+        if !source.exists() {
+            let mut report = CommitReport::default();
+            let excludes = Excludes::new(&repo_config.exclude)?;
+            copy_entry(self, &repo_config, &excludes, &key, &source, &mut report)?;
+            if !report.missing.is_empty() {
+                return Err(LdfmError::Validation(format!(
+                    "Neither {} nor its copy in {} repository exist.",
+                    target.display(),
+                    repo.name
+                )));
+            }
+        }
+        Ok(EditTarget {
+            repo: repo.name.clone(),
+            key,
+            source,
+        })
+    }
+
+    /// Apply a single entry from its repository copy to this machine.
+    pub fn apply_entry(&self, repo: &str, key: &str) -> Result<ApplyReport> {
+        let mut plan = self.plan_apply()?;
+        plan.actions
+            .retain(|action| action.repo == repo && action.key == key);
+        plan.conflicts.clear();
+        self.apply_plan(&plan)
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    errors::{IoContext, LdfmError, Result},
+    manager::resolve_path,
+    utils::{home_dir, walk_dir},
+};
+
+/// Heredoc delimiter of files in exported scripts.
+const HEREDOC_DELIMITER: &str = "LDFM_EOF";
+
+/// Formats supported by [`Ldfm::export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ExportFormat {
+    /// Gzipped tarball with paths relative to the home directory.
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    /// POSIX shell script recreating the files.
+    #[serde(rename = "sh")]
+    Sh,
+}
+
+impl ExportFormat {
+    /// Detect the format from the extension of the output file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".sh") {
+            Some(Self::Sh)
+        } else {
+            None
+        }
+    }
+}
+
+/// Result of [`Ldfm::export`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportReport {
+    /// Number of exported files.
+    pub files: usize,
+    /// Keys of entries that manage only a part of a file and can't be exported.
+    pub skipped: Vec<String>,
+}
+
+/// A file to export.
+struct ExportedFile {
+    source: PathBuf,
+    mode: u32,
+}
+
+impl Ldfm {
+    /// Export repository copies of tracked files into a single artifact,
+    /// so they can be applied on machines without git or network access.
+    ///
+    /// Files are exported the way `apply` would write them, so files of
+    /// higher priority repositories win. Blocks and merged keys depend
+    /// on the files of the target machine and are skipped.
+    pub fn export(&self, format: ExportFormat, output: &Path) -> Result<ExportReport> {
+        let home = home_dir().ok_or(LdfmError::Validation(
+            "Cannot get home directory. Set HOME or LDFM_HOME.".to_string(),
+        ))?;
+        let output = resolve_path(output)?;
+        let mut report = ExportReport::default();
+        let mut files = BTreeMap::new();
+        for action in self.plan_apply()?.actions {
+            if action.entry.partial().is_some() {
+                report.skipped.push(action.key);
+                continue;
+            }
+            let Ok(target) = action.target.strip_prefix(&home) else {
+                tracing::warn!(
+                    "{} is outside of the home directory, skipping.",
+                    action.target.display()
+                );
+                report.skipped.push(action.key);
+                continue;
+            };
+            let sources = if action.source.is_dir() {
+                walk_dir(&action.source, true)?
+                    .into_iter()
+                    .map(|relative| (action.source.join(&relative), target.join(relative)))
+                    .collect()
+            } else if action.source.exists() {
+                vec![(action.source.clone(), target.to_path_buf())]
+            } else {
+                tracing::warn!("{} was never committed, skipping.", action.key);
+                report.skipped.push(action.key);
+                continue;
+            };
+            for (source, target) in sources {
+                let mode = file_mode(&source)?;
+                files.insert(target, ExportedFile { source, mode });
+            }
+        }
+        report.files = files.len();
+        let file = File::create(&output).with_path("create", &output)?;
+        match format {
+            ExportFormat::TarGz => write_tarball(file, &files),
+            ExportFormat::Sh => write_script(file, &files),
+        }
+        .with_path("write", &output)?;
+        Ok(report)
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let metadata = std::fs::metadata(path).with_path("read", path)?;
+    Ok(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Result<u32> {
+    Ok(0o644)
+}
+
+fn write_tarball(file: File, files: &BTreeMap<PathBuf, ExportedFile>) -> std::io::Result<()> {
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    for (target, file) in files {
+        let mut header = tar::Header::new_gnu();
+        let metadata = std::fs::metadata(&file.source)?;
+        header.set_size(metadata.len());
+        header.set_mode(file.mode);
+        header.set_mtime(0);
+        archive.append_data(&mut header, target, File::open(&file.source)?)?;
+    }
+    archive.into_inner()?.finish()?.sync_all()
+}
+
+/// Write a POSIX shell script recreating the files in `$1` or `$HOME`.
+fn write_script(file: File, files: &BTreeMap<PathBuf, ExportedFile>) -> std::io::Result<()> {
+    let mut script = std::io::BufWriter::new(file);
+    writeln!(script, "#!/bin/sh")?;
+    writeln!(
+        script,
+        "# Dotfiles exported by ldfm. Usage: sh <script> [home directory]"
+    )?;
+    writeln!(script, "set -eu")?;
+    writeln!(script, "target=\"${{1:-$HOME}}\"")?;
+    for (target, file) in files {
+        let path = format!("\"$target\"/{}", shell_quote(&target.to_string_lossy()));
+        writeln!(script)?;
+        if let Some(parent) = target
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            writeln!(
+                script,
+                "mkdir -p \"$target\"/{}",
+                shell_quote(&parent.to_string_lossy())
+            )?;
+        }
+        let contents = std::fs::read(&file.source)?;
+        match std::str::from_utf8(&contents) {
+            Ok(text) if is_heredoc_safe(text) => {
+                writeln!(script, "cat > {} <<'{}'", path, HEREDOC_DELIMITER)?;
+                write!(script, "{}", text)?;
+                writeln!(script, "{}", HEREDOC_DELIMITER)?;
+            }
+            _ => {
+                writeln!(script, ": > {}", path)?;
+                for chunk in contents.chunks(512) {
+                    writeln!(script, "printf '{}' >> {}", printf_escape(chunk), path)?;
+                }
+            }
+        }
+        writeln!(script, "chmod {:o} {}", file.mode, path)?;
+    }
+    script.flush()
+}
+
+/// Text files ending with a newline are embedded as is.
+fn is_heredoc_safe(text: &str) -> bool {
+    text.ends_with('\n') && !text.lines().any(|line| line == HEREDOC_DELIMITER)
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Escape bytes for a single-quoted `printf` format string.
+fn printf_escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            b' '..=b'~' if !matches!(byte, b'\'' | b'\\' | b'%') => (*byte as char).to_string(),
+            byte => format!("\\{:03o}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_heredoc_safe, printf_escape, shell_quote};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(printf_escape(b"50% 'a'\n"), "50\\045 \\047a\\047\\012");
+        assert!(is_heredoc_safe("set number\n"));
+        assert!(!is_heredoc_safe("no newline"));
+        assert!(!is_heredoc_safe("LDFM_EOF\n"));
+    }
+}
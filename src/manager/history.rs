@@ -0,0 +1,275 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Ldfm,
+    errors::{IoContext, LdfmError, Result},
+    utils::{git_stdout, remove_path, state_dir},
+};
+
+/// A single `apply` recorded in the local history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Commits of the repositories that were applied.
+    pub commits: Vec<AppliedCommit>,
+    /// Files written by the apply.
+    pub files: Vec<WrittenFile>,
+    /// Directories created by the apply, parents first.
+    #[serde(default)]
+    pub dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedCommit {
+    pub repo: String,
+    /// `None` if the repository has no commits.
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrittenFile {
+    pub path: PathBuf,
+    /// SHA-256 of the file contents before the apply, under which
+    /// the backup is stored. `None` if the file didn't exist.
+    pub previous: Option<String>,
+}
+
+/// Result of [`Ldfm::rollback`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollbackReport {
+    /// Ids of the undone applies, newest first.
+    pub undone: Vec<u64>,
+    /// Files restored from backups.
+    pub restored: Vec<PathBuf>,
+    /// Files removed since they didn't exist before.
+    pub removed: Vec<PathBuf>,
+    /// Directories created by the undone applies that were empty and removed.
+    pub removed_dirs: Vec<PathBuf>,
+}
+
+impl Ldfm {
+    /// Applies recorded on this machine, oldest first.
+    pub fn history(&self) -> Result<Vec<HistoryEntry>> {
+        read_history(&history_dir()?)
+    }
+
+    /// Restore files to the state before the apply with the given id.
+    ///
+    /// The apply and all applies after it are undone, newest first,
+    /// and removed from the history. Without an id only the last apply is undone.
+    pub fn rollback(&self, id: Option<u64>) -> Result<RollbackReport> {
+        let dir = history_dir()?;
+        let mut history = read_history(&dir)?;
+        let id = match id {
+            Some(id) => id,
+            None => {
+                history
+                    .last()
+                    .ok_or(LdfmError::Validation(
+                        "There are no applies to roll back.".to_string(),
+                    ))?
+                    .id
+            }
+        };
+        let Some(position) = history.iter().position(|entry| entry.id == id) else {
+            return Err(LdfmError::Validation(format!(
+                "Apply {} is not in the history. Run `ldfm history` to list applies.",
+                id
+            )));
+        };
+        let mut report = RollbackReport::default();
+        for entry in history[position..].iter().rev() {
+            tracing::info!("Rolling back apply {}", entry.id);
+            for file in entry.files.iter().rev() {
+                match &file.previous {
+                    Some(hash) => {
+                        let backup = dir.join("backups").join(hash);
+                        if let Some(parent) = file.path.parent() {
+                            std::fs::create_dir_all(parent).with_path("create", parent)?;
+                        }
+                        // A directory may have replaced the file since.
+                        if file.path.is_dir() {
+                            remove_path(&file.path)?;
+                        }
+                        std::fs::copy(&backup, &file.path).with_path("restore", &file.path)?;
+                        report.restored.push(file.path.clone());
+                    }
+                    None => {
+                        remove_path(&file.path)?;
+                        report.removed.push(file.path.clone());
+                    }
+                }
+            }
+            for dir in entry.dirs.iter().rev() {
+                // Fails if something else was put into the directory since, which is fine.
+                if std::fs::remove_dir(dir).is_ok() {
+                    report.removed_dirs.push(dir.clone());
+                }
+            }
+            report.undone.push(entry.id);
+        }
+        history.truncate(position);
+        write_history(&dir, &history)?;
+        Ok(report)
+    }
+
+    /// Back up the files an apply is about to write and record it in the history
+    /// along with the directories it created for them.
+    ///
+    /// Returns the id of the new history entry.
+    pub(super) fn record_apply(&self, files: &[PathBuf], dirs: &[PathBuf]) -> Result<u64> {
+        let dir = history_dir()?;
+        let backups = dir.join("backups");
+        std::fs::create_dir_all(&backups).with_path("create", &backups)?;
+        let history = read_history(&dir)?;
+        let mut entry = HistoryEntry {
+            id: next_id(&dir, &history)?,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            commits: Vec::new(),
+            files: Vec::new(),
+            dirs: dirs.to_vec(),
+        };
+        for repo in self.repositories() {
+            let repo_path = repo.local_path.display().to_string();
+            let commit = match self.revision_commit(&repo.name) {
+                Some(commit) => Some(commit.to_string()),
+                None => git_stdout(["-C", &repo_path, "rev-parse", "HEAD"])
+                    .ok()
+                    .map(|stdout| String::from_utf8_lossy(&stdout).trim().to_string()),
+            };
+            entry.commits.push(AppliedCommit {
+                repo: repo.name.clone(),
+                commit,
+            });
+        }
+        for path in files {
+            if entry.files.iter().any(|file| &file.path == path) {
+                continue;
+            }
+            let previous = if path.is_file() {
+                let contents = std::fs::read(path).with_path("read", path)?;
+                let hash = format!("{:x}", Sha256::digest(&contents));
+                let backup = backups.join(&hash);
+                if !backup.exists() {
+                    std::fs::copy(path, &backup).with_path("back up", path)?;
+                }
+                Some(hash)
+            } else {
+                None
+            };
+            entry.files.push(WrittenFile {
+                path: path.clone(),
+                previous,
+            });
+        }
+        let id = entry.id;
+        let journal = dir.join("history.jsonl");
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal)
+            .with_path("open", &journal)?;
+        writeln!(file, "{}", to_json(&entry)?).with_path("write", &journal)?;
+        Ok(id)
+    }
+
+    /// Remove the history entry of an apply that failed and changed nothing.
+    pub(super) fn discard_apply(&self, id: u64) -> Result<()> {
+        let dir = history_dir()?;
+        let mut history = read_history(&dir)?;
+        history.retain(|entry| entry.id != id);
+        write_history(&dir, &history)
+    }
+}
+
+fn history_dir() -> Result<PathBuf> {
+    Ok(state_dir()
+        .ok_or(LdfmError::Validation(
+            "Cannot find the state directory. Set LDFM_STATE_DIR.".to_string(),
+        ))?
+        .join("history"))
+}
+
+/// Reserve the id of a new apply.
+///
+/// Ids are never reused, even after the applies are rolled back,
+/// so the last id ever given out is kept next to the history.
+fn next_id(dir: &Path, history: &[HistoryEntry]) -> Result<u64> {
+    let last_id = dir.join("last-id");
+    let recorded = if last_id.exists() {
+        std::fs::read_to_string(&last_id)
+            .with_path("read", &last_id)?
+            .trim()
+            .parse()
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    let id = history
+        .iter()
+        .map(|entry| entry.id)
+        .fold(recorded, u64::max)
+        + 1;
+    std::fs::write(&last_id, format!("{}\n", id)).with_path("write", &last_id)?;
+    Ok(id)
+}
+
+fn to_json(entry: &HistoryEntry) -> Result<String> {
+    serde_json::to_string(entry)
+        .map_err(|err| LdfmError::io("Cannot serialize the apply history", err))
+}
+
+fn read_history(dir: &Path) -> Result<Vec<HistoryEntry>> {
+    let journal = dir.join("history.jsonl");
+    if !journal.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = std::fs::read_to_string(&journal).with_path("read", &journal)?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|err| {
+                LdfmError::Validation(format!("Cannot parse {}: {}", journal.display(), err))
+            })
+        })
+        .collect()
+}
+
+/// Rewrite the history and remove backups no entry refers to.
+fn write_history(dir: &Path, history: &[HistoryEntry]) -> Result<()> {
+    let journal = dir.join("history.jsonl");
+    let mut contents = String::new();
+    for entry in history {
+        contents.push_str(&to_json(entry)?);
+        contents.push('\n');
+    }
+    std::fs::write(&journal, contents).with_path("write", &journal)?;
+    let backups = dir.join("backups");
+    if !backups.exists() {
+        return Ok(());
+    }
+    for backup in std::fs::read_dir(&backups).with_path("read", &backups)? {
+        let backup = backup.with_path("read", &backups)?;
+        let name = backup.file_name().to_string_lossy().to_string();
+        let used = history
+            .iter()
+            .flat_map(|entry| &entry.files)
+            .any(|file| file.previous.as_deref() == Some(name.as_str()));
+        if !used {
+            remove_path(&backup.path())?;
+        }
+    }
+    Ok(())
+}
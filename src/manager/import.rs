@@ -0,0 +1,467 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    errors::{IoContext, LdfmError, Result},
+    manager::resolve_path,
+    utils::{git_commit, git_stdout, walk_dir},
+};
+
+/// Directories shared by many applications. Imported files inside them
+/// are grouped into an entry per application instead of a single entry.
+const SHARED_DIRS: [&str; 5] = [
+    ".config",
+    ".local",
+    ".local/bin",
+    ".local/share",
+    ".local/state",
+];
+
+/// Dotfiles setups supported by [`Ldfm::import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportSource {
+    /// A GNU Stow directory with a subdirectory per package.
+    Stow,
+    /// A chezmoi source directory.
+    Chezmoi,
+    /// A yadm repository, usually `~/.local/share/yadm/repo.git`.
+    Yadm,
+    /// A bare git repository with the home directory as its work tree.
+    BareGit,
+}
+
+impl std::fmt::Display for ImportSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            Self::Stow => "stow",
+            Self::Chezmoi => "chezmoi",
+            Self::Yadm => "yadm",
+            Self::BareGit => "bare git repository",
+        };
+        f.write_str(source)
+    }
+}
+
+/// Result of [`Ldfm::import`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    /// Repository the files were imported to.
+    pub repo: String,
+    /// Keys of imported entries.
+    pub entries: Vec<String>,
+    /// Number of imported files.
+    pub files: usize,
+    /// Features of the source setup that could not be translated.
+    pub untranslated: Vec<String>,
+    /// Whether a new commit was created.
+    pub committed: bool,
+}
+
+/// A file of the imported setup.
+struct ImportedFile {
+    /// Path relative to the home directory.
+    target: PathBuf,
+    contents: Contents,
+    executable: bool,
+}
+
+enum Contents {
+    File(PathBuf),
+    /// A file committed to a git repository.
+    Git {
+        git_dir: PathBuf,
+        path: String,
+    },
+}
+
+impl Ldfm {
+    /// Import files of another dotfiles manager into the primary repository.
+    ///
+    /// Files are grouped into entries by their top-level file or directory in
+    /// the home directory, while `~/.config` and `~/.local` are split into
+    /// an entry per application. Imported files are committed, but not applied.
+    pub fn import(&self, source: ImportSource, path: &Path) -> Result<ImportReport> {
+        let path = resolve_path(path)?;
+        if !path.exists() {
+            return Err(LdfmError::Validation(format!(
+                "{} does not exist.",
+                path.display()
+            )));
+        }
+        let mut untranslated = Untranslated::default();
+        let files = match source {
+            ImportSource::Stow => stow_files(&path, &mut untranslated)?,
+            ImportSource::Chezmoi => chezmoi_files(&path, &mut untranslated)?,
+            ImportSource::Yadm => git_files(&path, true, &mut untranslated)?,
+            ImportSource::BareGit => git_files(&path, false, &mut untranslated)?,
+        };
+        let repo = self.primary_repo()?;
+        let mut repo_config = repo.get_repo_config()?;
+        let mut entries: BTreeMap<PathBuf, String> = BTreeMap::new();
+        for file in &files {
+            let prefix = entry_prefix(&file.target);
+            let key = match entries.get(&prefix) {
+                Some(key) => key.clone(),
+                None => {
+                    let key = repo_config.track_home_file(&prefix)?;
+                    entries.insert(prefix.clone(), key.clone());
+                    key
+                }
+            };
+            let mut destination = repo.local_path.join(repo_config.get_local_path(&key));
+            let relative = file.target.strip_prefix(&prefix).unwrap_or(Path::new(""));
+            if !relative.as_os_str().is_empty() {
+                destination = destination.join(relative);
+            }
+            tracing::debug!("Importing {} as {}", file.target.display(), key);
+            write_file(file, &destination)?;
+        }
+        repo.save_repo_config(&repo_config)?;
+        let committed = git_commit(
+            &repo.local_path.display().to_string(),
+            &format!("Imported dotfiles from {}.", source),
+        )?;
+        let mut entries = entries.into_values().collect::<Vec<_>>();
+        entries.sort();
+        entries.dedup();
+        Ok(ImportReport {
+            repo: repo.name.clone(),
+            entries,
+            files: files.len(),
+            untranslated: untranslated.0,
+            committed,
+        })
+    }
+}
+
+/// Untranslated features, each reported once.
+#[derive(Default)]
+struct Untranslated(Vec<String>);
+
+impl Untranslated {
+    fn push(&mut self, message: String) {
+        if !self.0.contains(&message) {
+            tracing::debug!("Untranslated: {}", message);
+            self.0.push(message);
+        }
+    }
+}
+
+/// Path of the entry the file belongs to, relative to the home directory.
+fn entry_prefix(target: &Path) -> PathBuf {
+    let mut prefix = PathBuf::new();
+    for component in target.components() {
+        prefix.push(component);
+        if !SHARED_DIRS.contains(&prefix.to_string_lossy().as_ref()) {
+            break;
+        }
+    }
+    prefix
+}
+
+/// Every package of a stow directory is stowed into the home directory.
+fn stow_files(stow_dir: &Path, untranslated: &mut Untranslated) -> Result<Vec<ImportedFile>> {
+    let mut files = Vec::new();
+    for name in [".stowrc", ".stow-global-ignore", ".stow-local-ignore"] {
+        if stow_dir.join(name).exists() {
+            untranslated.push(format!(
+                "{name}: stow options and ignore lists are not imported"
+            ));
+        }
+    }
+    for package in walk_dir(stow_dir, false)? {
+        let package_dir = stow_dir.join(&package);
+        if !package_dir.is_dir() || package.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        for relative in walk_dir(&package_dir, true)? {
+            if relative
+                .file_name()
+                .is_some_and(|name| name == ".stow-local-ignore")
+            {
+                untranslated.push(format!(
+                    "{}: stow ignore lists are not imported",
+                    package.join(&relative).display()
+                ));
+                continue;
+            }
+            // Stow's `--dotfiles` option turns `dot-` prefixes into dots.
+            let target = relative
+                .components()
+                .map(|component| {
+                    let name = component.as_os_str().to_string_lossy();
+                    match name.strip_prefix("dot-") {
+                        Some(name) => format!(".{name}"),
+                        None => name.to_string(),
+                    }
+                })
+                .collect();
+            files.push(ImportedFile {
+                target,
+                contents: Contents::File(package_dir.join(&relative)),
+                executable: false,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Files of a chezmoi source directory with attributes encoded in their names.
+fn chezmoi_files(source_dir: &Path, untranslated: &mut Untranslated) -> Result<Vec<ImportedFile>> {
+    let mut root = source_dir.to_path_buf();
+    let chezmoiroot = source_dir.join(".chezmoiroot");
+    if chezmoiroot.exists() {
+        let contents = std::fs::read_to_string(&chezmoiroot).with_path("read", &chezmoiroot)?;
+        root = source_dir.join(contents.trim());
+    }
+    let mut files = Vec::new();
+    'files: for relative in walk_dir(&root, true)? {
+        let mut target = PathBuf::new();
+        let mut executable = false;
+        for component in relative.components() {
+            let name = component.as_os_str().to_string_lossy();
+            if name.starts_with(".chezmoi") {
+                untranslated.push(format!("{}: chezmoi special files are not supported", name));
+                continue 'files;
+            }
+            let attributes = ChezmoiName::parse(&name);
+            if let Some(feature) = attributes.unsupported {
+                untranslated.push(format!(
+                    "{}: {} are not supported",
+                    relative.display(),
+                    feature
+                ));
+                continue 'files;
+            }
+            for note in attributes.notes {
+                untranslated.push(note.to_string());
+            }
+            executable |= attributes.executable;
+            target.push(attributes.name);
+        }
+        files.push(ImportedFile {
+            target,
+            contents: Contents::File(root.join(&relative)),
+            executable,
+        });
+    }
+    Ok(files)
+}
+
+/// A file or directory name in the chezmoi source state.
+struct ChezmoiName {
+    name: String,
+    executable: bool,
+    /// Feature preventing the import of the file.
+    unsupported: Option<&'static str>,
+    /// Attributes that are lost during the import.
+    notes: Vec<&'static str>,
+}
+
+impl ChezmoiName {
+    fn parse(source: &str) -> Self {
+        let mut result = Self {
+            name: String::new(),
+            executable: false,
+            unsupported: None,
+            notes: Vec::new(),
+        };
+        let mut name = source;
+        let mut dot = false;
+        while let Some((prefix, rest)) = name.split_once('_') {
+            match prefix {
+                "literal" => {
+                    name = rest;
+                    break;
+                }
+                "dot" => dot = true,
+                "executable" => result.executable = true,
+                "empty" | "once" | "onchange" | "before" | "after" => {}
+                "create" => result
+                    .notes
+                    .push("create_ files are imported as regular files and always applied"),
+                "private" | "readonly" => result
+                    .notes
+                    .push("private_ and readonly_ permissions are not preserved"),
+                "exact" => result
+                    .notes
+                    .push("exact_ directories are imported as regular directories"),
+                "encrypted" => result.unsupported = Some("encrypted files"),
+                "run" => result.unsupported = Some("scripts"),
+                "modify" => result.unsupported = Some("modify scripts"),
+                "remove" => result.unsupported = Some("removals"),
+                "symlink" => result.unsupported = Some("symlinks"),
+                "external" => result.unsupported = Some("externals"),
+                _ => break,
+            }
+            name = rest;
+        }
+        if let Some(stripped) = name.strip_suffix(".tmpl") {
+            result.unsupported.get_or_insert("templates");
+            name = stripped;
+        }
+        let name = name.strip_suffix(".literal").unwrap_or(name);
+        result.name = if dot {
+            format!(".{name}")
+        } else {
+            name.to_string()
+        };
+        result
+    }
+}
+
+/// Files committed to a bare repository with the home directory as its work tree.
+///
+/// For yadm, only `##default` variants of alternate files are imported.
+fn git_files(
+    git_dir: &Path,
+    yadm: bool,
+    untranslated: &mut Untranslated,
+) -> Result<Vec<ImportedFile>> {
+    let git_dir_arg = git_dir.as_os_str();
+    let listing = git_stdout([
+        "--git-dir".as_ref(),
+        git_dir_arg,
+        "ls-tree".as_ref(),
+        "-r".as_ref(),
+        "-z".as_ref(),
+        "HEAD".as_ref(),
+    ])?;
+    let mut files = Vec::new();
+    for line in String::from_utf8_lossy(&listing).split('\0') {
+        let Some((info, path)) = line.split_once('\t') else {
+            continue;
+        };
+        let mode = info.split(' ').next().unwrap_or_default();
+        match mode {
+            "100644" | "100755" => {}
+            "120000" => {
+                untranslated.push(format!("{path}: symlinks are not supported"));
+                continue;
+            }
+            _ => {
+                untranslated.push(format!("{path}: submodules are not supported"));
+                continue;
+            }
+        }
+        let mut target = PathBuf::from(path);
+        if yadm {
+            if path.starts_with(".config/yadm/") {
+                untranslated.push(format!(
+                    "{path}: yadm configuration, bootstrap and encryption are not imported"
+                ));
+                continue;
+            }
+            let Some(alt) = yadm_alternate(path) else {
+                untranslated.push(format!(
+                    "{path}: only ##default alternate files are imported"
+                ));
+                continue;
+            };
+            target = alt;
+        }
+        files.push(ImportedFile {
+            target,
+            contents: Contents::Git {
+                git_dir: git_dir.to_path_buf(),
+                path: path.to_string(),
+            },
+            executable: mode == "100755",
+        });
+    }
+    Ok(files)
+}
+
+/// Path of the yadm alternate file without conditions.
+///
+/// Returns `None` for variants other than `##default`.
+fn yadm_alternate(path: &str) -> Option<PathBuf> {
+    let mut target = PathBuf::new();
+    for component in path.split('/') {
+        match component.split_once("##") {
+            Some((name, "default")) => target.push(name),
+            Some(_) => return None,
+            None => target.push(component),
+        }
+    }
+    Some(target)
+}
+
+fn write_file(file: &ImportedFile, destination: &Path) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent).with_path("create", parent)?;
+    }
+    match &file.contents {
+        Contents::File(source) => {
+            std::fs::copy(source, destination).with_path("copy", source)?;
+        }
+        Contents::Git { git_dir, path } => {
+            let contents = git_stdout([
+                "--git-dir".as_ref(),
+                git_dir.as_os_str(),
+                "show".as_ref(),
+                format!("HEAD:{path}").as_ref(),
+            ])?;
+            std::fs::write(destination, contents).with_path("write", destination)?;
+        }
+    }
+    #[cfg(unix)]
+    if file.executable {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(destination, std::fs::Permissions::from_mode(0o755))
+            .with_path("write", destination)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{ChezmoiName, entry_prefix, yadm_alternate};
+
+    #[test]
+    fn chezmoi_names() {
+        let name = ChezmoiName::parse("private_executable_dot_script.sh");
+        assert_eq!(name.name, ".script.sh");
+        assert!(name.executable);
+        assert!(name.unsupported.is_none());
+        assert_eq!(ChezmoiName::parse("literal_dot_file").name, "dot_file");
+        assert_eq!(
+            ChezmoiName::parse("dot_gitconfig.tmpl").unsupported,
+            Some("templates")
+        );
+        assert_eq!(
+            ChezmoiName::parse("run_once_install.sh").unsupported,
+            Some("scripts")
+        );
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(entry_prefix(Path::new(".bashrc")), PathBuf::from(".bashrc"));
+        assert_eq!(
+            entry_prefix(Path::new(".config/nvim/init.lua")),
+            PathBuf::from(".config/nvim")
+        );
+        assert_eq!(
+            entry_prefix(Path::new(".local/bin/tool")),
+            PathBuf::from(".local/bin/tool")
+        );
+    }
+
+    #[test]
+    fn yadm_alternates() {
+        assert_eq!(
+            yadm_alternate(".config/app##default/config"),
+            Some(PathBuf::from(".config/app/config"))
+        );
+        assert_eq!(yadm_alternate(".bashrc##os.Linux"), None);
+    }
+}
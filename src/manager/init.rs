@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    Ldfm,
+    configs::{LdfmConfig, RepoConfig, Repository},
+    errors::{IoContext, LdfmError, Result},
+    utils::{git_clone, git_commit, git_init, git_lfs_pull, git_remote_add, write_toml},
+};
+
+const DEFAULT_GITIGNORE: &str = "\
+# Editor and OS leftovers that should never end up in dotfiles.
+*.swp
+*~
+.DS_Store
+";
+
+const DEFAULT_GITATTRIBUTES: &str = "\
+# Keep line endings stable between machines.
+* text=auto eol=lf
+";
+
+/// Options of [`Ldfm::init`].
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    /// Name of the repository in the ldfm config.
+    pub name: String,
+    /// Priority of the repository, see [`Repository::priority`].
+    pub priority: i32,
+    /// Where the repository lives on this machine.
+    pub local_path: PathBuf,
+    /// Remote to clone, or to add as `origin` when `new` is set.
+    pub git_repo: Option<String>,
+    /// Create a fresh repository instead of cloning or reusing an existing one.
+    pub new: bool,
+    /// Default branch of a fresh repository.
+    pub branch: String,
+    /// Reinitialize over an existing configuration or repository.
+    pub force: bool,
+}
+
+impl Ldfm {
+    /// Add a repository to the ldfm config, cloning or creating it if needed,
+    /// and save the config to `config_path`.
+    pub fn init(
+        config_path: &Path,
+        config: Option<LdfmConfig>,
+        options: InitOptions,
+    ) -> Result<Self> {
+        init(config_path, config, options)
+    }
+}
+
+fn init(config_path: &Path, config: Option<LdfmConfig>, options: InitOptions) -> Result<Ldfm> {
+    let InitOptions {
+        name,
+        priority,
+        local_path,
+        git_repo,
+        new,
+        branch,
+        force,
+    } = options;
+    let mut config = config.unwrap_or_default();
+    let repo = Repository {
+        name,
+        local_path: std::path::absolute(&local_path).with_path("resolve", &local_path)?,
+        priority,
+    };
+    if let Some(existing) = config.repos.iter().find(|r| r.name == repo.name)
+        && existing.local_path != repo.local_path
+        && !force
+    {
+        return Err(LdfmError::Conflict(format!(
+            "Repository {} is already initialized at {}. Use `--force` to reinitialize it or `--name` to add another repository.",
+            existing.name,
+            existing.local_path.display()
+        )));
+    }
+    if new {
+        create_repo(&local_path, git_repo.as_deref(), &branch, force)?;
+    } else if let Some(repo_uri) = git_repo {
+        tracing::info!("Cloning repository from {}", repo_uri);
+        git_clone(&repo_uri, &local_path.to_string_lossy())?;
+        git_lfs_pull(&local_path.to_string_lossy())?;
+    } else {
+        if !local_path.join(".git").exists() {
+            return Err(LdfmError::Validation(format!(
+                "{} is not a git repository. Use `--new` to create a fresh one.",
+                local_path.display()
+            )));
+        }
+        tracing::info!("Using existing git repository at {}", local_path.display());
+    };
+
+    let repo_config = local_path.join("ldfm.toml");
+    if !repo_config.exists() {
+        tracing::info!(
+            "Creating repository configuration at {}",
+            repo_config.display()
+        );
+        write_toml(&repo_config, &RepoConfig::default())?;
+        git_commit(&local_path.to_string_lossy(), "Initialized ldfm")?;
+    }
+
+    config.set_repo(repo);
+    tracing::info!("Writing configuration to {}", config_path.display());
+    config.save(config_path)?;
+    Ldfm::open(config)
+}
+
+/// Create a brand new dotfiles repository at `local_path`.
+///
+/// The directory is created if needed, initialized as a git repository
+/// with the given default branch and populated with `.gitignore` and
+/// `.gitattributes`. If `remote` is set, it's added as `origin` and used as
+/// the upstream of the default branch.
+fn create_repo(local_path: &Path, remote: Option<&str>, branch: &str, force: bool) -> Result<()> {
+    if local_path.join(".git").exists() && !force {
+        return Err(LdfmError::Conflict(format!(
+            "{} is already a git repository. Use `--force` to reinitialize it.",
+            local_path.display()
+        )));
+    }
+    tracing::info!("Creating new repository at {}", local_path.display());
+    std::fs::create_dir_all(local_path).with_path("create", local_path)?;
+    let repo = local_path.to_string_lossy();
+    git_init(&repo, branch)?;
+    for (name, contents) in [
+        (".gitignore", DEFAULT_GITIGNORE),
+        (".gitattributes", DEFAULT_GITATTRIBUTES),
+    ] {
+        let path = local_path.join(name);
+        if !path.exists() {
+            tracing::info!("Writing {}", path.display());
+            std::fs::write(&path, contents).with_path("write", &path)?;
+        }
+    }
+    if let Some(remote) = remote {
+        tracing::info!("Adding remote origin {}", remote);
+        git_remote_add(&repo, "origin", remote, branch)?;
+    }
+    Ok(())
+}
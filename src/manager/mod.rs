@@ -0,0 +1,143 @@
+mod apply;
+mod commit;
+mod edit;
+mod export;
+mod history;
+mod import;
+mod init;
+mod overlay;
+mod rename;
+mod revision;
+mod track;
+mod transaction;
+mod unmanaged;
+mod watch;
+
+pub use apply::{ApplyAction, ApplyConflict, ApplyPlan, ApplyReport};
+pub use commit::CommitReport;
+pub use edit::EditTarget;
+pub use export::{ExportFormat, ExportReport};
+pub use history::{AppliedCommit, HistoryEntry, RollbackReport, WrittenFile};
+pub use import::{ImportReport, ImportSource};
+pub use init::InitOptions;
+pub use overlay::OverlayOutcome;
+pub use rename::{MoveOutcome, RenameOutcome};
+pub use track::{TrackOutcome, UntrackOptions, UntrackOutcome};
+pub use unmanaged::UnmanagedEntry;
+pub use watch::WatchTarget;
+
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    configs::{LdfmConfig, Repository},
+    errors::{IoContext, LdfmError, Result},
+    progress::{NoProgress, Progress},
+    report::EntryReport,
+    utils::expand_tilde,
+};
+
+/// Entry point of the ldfm API.
+///
+/// Wraps the ldfm config and optionally narrows all operations
+/// down to a single repository.
+#[derive(Debug, Clone)]
+pub struct Ldfm {
+    config: LdfmConfig,
+    selected: Option<String>,
+    /// Snapshot of repositories at a git revision, if one was requested.
+    revision: Option<Arc<revision::Revision>>,
+    progress: Arc<dyn Progress>,
+}
+
+impl Ldfm {
+    /// Open ldfm with the given config.
+    pub fn open(config: LdfmConfig) -> Result<Self> {
+        if config.repos.is_empty() {
+            return Err(LdfmError::Validation(
+                "No repositories are configured. Run `ldfm init` first.".to_string(),
+            ));
+        }
+        Ok(Self {
+            config,
+            selected: None,
+            revision: None,
+            progress: Arc::new(NoProgress),
+        })
+    }
+
+    /// Operate only on the repository with the given name.
+    ///
+    /// `None` resets the selection to all repositories.
+    pub fn select(mut self, repo: Option<String>) -> Result<Self> {
+        if let Some(name) = &repo {
+            self.config.get_repo(Some(name))?;
+        }
+        self.selected = repo;
+        Ok(self)
+    }
+
+    /// Report progress of `apply` and `commit` to the given receiver.
+    pub fn with_progress(mut self, progress: impl Progress + 'static) -> Self {
+        self.progress = Arc::new(progress);
+        self
+    }
+
+    pub(super) fn progress(&self) -> &dyn Progress {
+        self.progress.as_ref()
+    }
+
+    /// How many files to compare and copy in parallel.
+    pub(super) fn jobs(&self) -> usize {
+        self.config
+            .jobs
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    pub fn config(&self) -> &LdfmConfig {
+        &self.config
+    }
+
+    /// Repositories to operate on, ordered from the highest priority to the lowest.
+    pub fn repositories(&self) -> Vec<&Repository> {
+        self.config
+            .selected_repos(self.selected.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Repository that receives newly tracked files:
+    /// the selected one or the one with the highest priority.
+    pub fn primary_repo(&self) -> Result<&Repository> {
+        self.config.get_repo(self.selected.as_deref())
+    }
+
+    /// Describe all tracked entries.
+    pub fn list(&self) -> Result<Vec<EntryReport>> {
+        let mut reports = Vec::new();
+        for repo in self.repositories() {
+            reports.extend(EntryReport::collect(repo, &repo.get_repo_config()?)?);
+        }
+        Ok(reports)
+    }
+
+    /// Describe all tracked entries along with their sync state.
+    pub fn status(&self) -> Result<Vec<EntryReport>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(EntryReport::with_state)
+            .collect())
+    }
+}
+
+/// Expand tilde and make the path absolute.
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    let path = expand_tilde(path).ok_or(LdfmError::Validation(
+        "Cannot expand tilde from path".to_string(),
+    ))?;
+    std::path::absolute(&path).with_path("resolve", &path)
+}
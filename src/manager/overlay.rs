@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::{HOSTS_DIR, Repository},
+    errors::{IoContext, LdfmError, Result},
+    excludes::Excludes,
+    manager::{CommitReport, commit::copy_entry, resolve_path},
+    utils::{git_commit_paths, hostname, remove_path},
+};
+
+/// Host-specific copy of an entry, see [`Ldfm::make_host_local`].
+#[derive(Debug, Clone, Serialize)]
+pub struct OverlayOutcome {
+    pub repo: String,
+    pub key: String,
+    /// Name of the host the copy belongs to.
+    pub host: String,
+    /// Host-specific copy of the entry in the repository.
+    pub path: PathBuf,
+}
+
+impl Ldfm {
+    /// Keep the entry tracking the path in a copy specific to this host.
+    ///
+    /// From now on `commit` writes the entry to `hosts/<host>/<key>`
+    /// of the repository and `apply` prefers this copy over the shared one
+    /// on this host. Other hosts keep using the shared copy.
+    pub fn make_host_local(&self, path: &Path) -> Result<OverlayOutcome> {
+        let (repo, key, overlay) = self.find_overlay(path)?;
+        if !overlay.path.exists() {
+            let repo_config = repo.get_repo_config()?;
+            let excludes = Excludes::new(&repo_config.exclude)?;
+            let mut report = CommitReport::default();
+            copy_entry(
+                self,
+                &repo_config,
+                &excludes,
+                &key,
+                &overlay.path,
+                &mut report,
+            )?;
+            if !report.missing.is_empty() {
+                return Err(LdfmError::Validation(format!(
+                    "File {} does not exist.",
+                    path.display()
+                )));
+            }
+        }
+        Ok(overlay)
+    }
+
+    /// Replace the shared copy of the entry with the copy specific to this host
+    /// and commit the repository.
+    pub fn promote_overlay(&self, path: &Path) -> Result<OverlayOutcome> {
+        let (repo, key, overlay) = self.find_overlay(path)?;
+        if !overlay.path.exists() {
+            return Err(LdfmError::Validation(format!(
+                "File {} has no copy specific to {}.",
+                path.display(),
+                overlay.host
+            )));
+        }
+        let repo_config = repo.get_repo_config()?;
+        let shared_path = repo_config.get_local_path(&key);
+        let shared = repo.local_path.join(&shared_path);
+        remove_path(&shared)?;
+        if let Some(parent) = shared.parent() {
+            std::fs::create_dir_all(parent).with_path("create", parent)?;
+        }
+        std::fs::rename(&overlay.path, &shared).with_path("move", &overlay.path)?;
+        let host_dir = repo.local_path.join(HOSTS_DIR).join(&overlay.host);
+        for dir in [host_dir.as_path(), &repo.local_path.join(HOSTS_DIR)] {
+            // Fails if other copies are left, which is fine.
+            std::fs::remove_dir(dir).ok();
+        }
+        // Other pending changes in the repository are left uncommitted.
+        git_commit_paths(
+            &repo.local_path.display().to_string(),
+            &format!("Promoted {} from {}.", key, overlay.host),
+            &[shared_path, repo_config.overlay_path(&key, &overlay.host)],
+        )?;
+        Ok(overlay)
+    }
+
+    fn find_overlay(&self, path: &Path) -> Result<(&Repository, String, OverlayOutcome)> {
+        let host = hostname().ok_or(LdfmError::Validation(
+            "Cannot detect the host name. Set LDFM_HOST.".to_string(),
+        ))?;
+        let target = resolve_path(path)?;
+        let not_tracked =
+            || LdfmError::Validation(format!("File {} is not tracked.", target.display()));
+        let repo = self.find_owner(&target, None)?.ok_or_else(not_tracked)?;
+        let repo_config = repo.get_repo_config()?;
+        let key = repo_config
+            .find_key(&target, None)?
+            .ok_or_else(not_tracked)?;
+        let path = repo.local_path.join(repo_config.overlay_path(&key, &host));
+        let overlay = OverlayOutcome {
+            repo: repo.name.clone(),
+            key: key.clone(),
+            host,
+            path,
+        };
+        Ok((repo, key, overlay))
+    }
+}
+
+/// Remove copies of the entry specific to any host.
+///
+/// Returns paths of the removed copies, relative to the repository.
+pub(super) fn remove_overlays(repo: &Repository, key: &str) -> Result<Vec<PathBuf>> {
+    let hosts = repo.local_path.join(HOSTS_DIR);
+    let mut removed = Vec::new();
+    if !hosts.is_dir() {
+        return Ok(removed);
+    }
+    for host in std::fs::read_dir(&hosts).with_path("read", &hosts)? {
+        let host = host.with_path("read", &hosts)?;
+        let overlay = host.path().join(key);
+        if overlay.exists() {
+            remove_path(&overlay)?;
+            removed.push(Path::new(HOSTS_DIR).join(host.file_name()).join(key));
+        }
+        std::fs::remove_dir(host.path()).ok();
+    }
+    std::fs::remove_dir(&hosts).ok();
+    Ok(removed)
+}
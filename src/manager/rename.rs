@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::{HOSTS_DIR, RepoConfig, Repository},
+    errors::{IoContext, LdfmError, Result},
+    manager::resolve_path,
+    utils::{git, run_git},
+};
+
+/// Result of [`Ldfm::move_target`].
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveOutcome {
+    pub repo: String,
+    /// Keys of the entries tracking the moved path or paths inside it.
+    pub keys: Vec<String>,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Whether the file on this machine was moved.
+    /// `false` if it was already moved by hand.
+    pub moved_live: bool,
+}
+
+/// Result of [`Ldfm::rename_key`].
+#[derive(Debug, Clone, Serialize)]
+pub struct RenameOutcome {
+    pub repo: String,
+    pub from: String,
+    pub to: String,
+    /// Copies of the entry moved in the repository.
+    pub moved_copies: Vec<PathBuf>,
+}
+
+impl Ldfm {
+    /// Move a tracked file or directory on this machine and update entries tracking it
+    /// or anything inside it.
+    ///
+    /// Keys of the entries stay the same. If `from` doesn't exist anymore,
+    /// only the entries are updated, so files moved by hand can be caught up with.
+    pub fn move_target(&self, from: &Path, to: &Path) -> Result<MoveOutcome> {
+        let from = resolve_path(from)?;
+        let to = resolve_path(to)?;
+        let mut owners = Vec::new();
+        for repo in self.repositories() {
+            let repo_config = repo.get_repo_config()?;
+            if repo_config.is_tracked(&to)? {
+                return Err(LdfmError::Conflict(format!(
+                    "File {} is already tracked in {} repository.",
+                    to.display(),
+                    repo.name
+                )));
+            }
+            if repo_config.tracks_within(&from)? {
+                owners.push((repo, repo_config));
+            }
+        }
+        let (repo, mut repo_config) = match owners.len() {
+            0 => {
+                return Err(LdfmError::Validation(format!(
+                    "File {} is not tracked.",
+                    from.display()
+                )));
+            }
+            1 => owners.remove(0),
+            _ => {
+                return Err(LdfmError::Conflict(format!(
+                    "File {} is tracked in several repositories. Use `--repo` to choose one.",
+                    from.display()
+                )));
+            }
+        };
+        if to.exists() || to.is_symlink() {
+            return Err(LdfmError::Conflict(format!(
+                "File {} already exists.",
+                to.display()
+            )));
+        }
+        let keys = repo_config.move_target(&from, &to)?;
+        let moved_live = from.exists() || from.is_symlink();
+        if moved_live {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent).with_path("create", parent)?;
+            }
+            std::fs::rename(&from, &to).with_path("move", &from)?;
+        }
+        repo.save_repo_config(&repo_config)?;
+        Ok(MoveOutcome {
+            repo: repo.name.clone(),
+            keys,
+            from,
+            to,
+            moved_live,
+        })
+    }
+
+    /// Change the key of an entry, moving its copies in the repository with `git mv`.
+    pub fn rename_key(&self, from: &str, to: &str) -> Result<RenameOutcome> {
+        validate_key(to)?;
+        let mut owners = Vec::new();
+        for repo in self.repositories() {
+            let repo_config = repo.get_repo_config()?;
+            if repo_config.files.contains_key(from) {
+                owners.push((repo, repo_config));
+            }
+        }
+        let (repo, mut repo_config) = match owners.len() {
+            0 => {
+                return Err(LdfmError::Validation(format!(
+                    "Key {} is not tracked.",
+                    from
+                )));
+            }
+            1 => owners.remove(0),
+            _ => {
+                return Err(LdfmError::Conflict(format!(
+                    "Key {} is tracked in several repositories. Use `--repo` to choose one.",
+                    from
+                )));
+            }
+        };
+        if repo_config.files.contains_key(to) {
+            return Err(LdfmError::Conflict(format!(
+                "Key {} is already tracked in {} repository.",
+                to, repo.name
+            )));
+        }
+        let mut copies = vec![(
+            repo_config.get_local_path(from),
+            repo_config.get_local_path(to),
+        )];
+        let hosts = repo.local_path.join(HOSTS_DIR);
+        if hosts.is_dir() {
+            for host in std::fs::read_dir(&hosts).with_path("read", &hosts)? {
+                let host = host.with_path("read", &hosts)?.file_name();
+                let host = host.to_string_lossy();
+                copies.push((
+                    repo_config.overlay_path(from, &host),
+                    repo_config.overlay_path(to, &host),
+                ));
+            }
+        }
+        copies.retain(|(source, _)| repo.local_path.join(source).exists());
+        for (_, destination) in &copies {
+            let destination = repo.local_path.join(destination);
+            if destination.exists() {
+                return Err(LdfmError::Conflict(format!(
+                    "File {} already exists in the repository.",
+                    destination.display()
+                )));
+            }
+        }
+        let mut moved_copies = Vec::new();
+        for (source, destination) in copies {
+            move_copy(repo, &source, &destination)?;
+            moved_copies.push(repo.local_path.join(destination));
+        }
+        let entry = repo_config
+            .files
+            .remove(from)
+            .expect("the key was checked above");
+        repo_config.files.insert(to.to_string(), entry);
+        repo.save_repo_config(&repo_config)?;
+        Ok(RenameOutcome {
+            repo: repo.name.clone(),
+            from: from.to_string(),
+            to: to.to_string(),
+            moved_copies,
+        })
+    }
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    if RepoConfig::is_reserved_key(key) {
+        return Err(LdfmError::Validation(format!(
+            "{} can't be used as a key. Keys must be plain file names.",
+            key
+        )));
+    }
+    Ok(())
+}
+
+/// Move a copy inside the repository, with `git mv` if git tracks it.
+fn move_copy(repo: &Repository, source: &Path, destination: &Path) -> Result<()> {
+    let repo_path = repo.local_path.display().to_string();
+    let source = source.display().to_string();
+    let destination_path = repo.local_path.join(destination);
+    if let Some(parent) = destination_path.parent() {
+        std::fs::create_dir_all(parent).with_path("create", parent)?;
+    }
+    let tracked = run_git(["-C", &repo_path, "ls-files", "--error-unmatch", &source])?
+        .status
+        .success();
+    if tracked {
+        git([
+            "-C",
+            &repo_path,
+            "mv",
+            &source,
+            &destination.display().to_string(),
+        ])
+    } else {
+        let source = repo.local_path.join(source);
+        std::fs::rename(&source, &destination_path).with_path("move", &source)
+    }
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    Ldfm,
+    errors::{IoContext, LdfmError, Result},
+    utils::git_stdout,
+};
+
+/// Snapshot of repositories at a git revision, see [`Ldfm::at_revision`].
+#[derive(Debug)]
+pub(super) struct Revision {
+    /// Commits the revision resolved to, by repository name.
+    pub commits: HashMap<String, String>,
+    /// Directory with the extracted repositories. Removed on drop.
+    _dir: tempfile::TempDir,
+}
+
+impl Ldfm {
+    /// Read repositories at the given commit, branch or tag instead of their working trees.
+    ///
+    /// Files of every selected repository are extracted into a temporary
+    /// directory, leaving the checkout untouched. The returned instance is
+    /// meant for [`Ldfm::plan_apply`], [`Ldfm::apply_plan`] and [`Ldfm::status`].
+    ///
+    /// Repositories without the revision are left out with a warning.
+    /// It's an error if none of the selected repositories has it.
+    pub fn at_revision(mut self, rev: &str) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("ldfm-rev-")
+            .tempdir()
+            .map_err(|err| LdfmError::io("Cannot create a temporary directory", err))?;
+        let mut commits = HashMap::new();
+        let mut missing = Vec::new();
+        let selected = self
+            .repositories()
+            .into_iter()
+            .map(|repo| repo.name.clone())
+            .collect::<Vec<_>>();
+        for repo in self
+            .config
+            .repos
+            .iter_mut()
+            .filter(|repo| selected.contains(&repo.name))
+        {
+            let repo_path = repo.local_path.display().to_string();
+            let spec = format!("{}^{{commit}}", rev);
+            let Ok(commit) =
+                git_stdout(["-C", &repo_path, "rev-parse", "--verify", "--quiet", &spec])
+            else {
+                missing.push(repo.name.clone());
+                continue;
+            };
+            let commit = String::from_utf8_lossy(&commit).trim().to_string();
+            tracing::info!("Reading {} repository at {}", repo.name, commit);
+            let archive = git_stdout(["-C", &repo_path, "archive", "--format=tar", &commit])?;
+            let snapshot = dir.path().join(&repo.name);
+            tar::Archive::new(archive.as_slice())
+                .unpack(&snapshot)
+                .with_path("extract", &snapshot)?;
+            repo.local_path = snapshot;
+            commits.insert(repo.name.clone(), commit);
+        }
+        if commits.is_empty() {
+            return Err(LdfmError::Validation(match missing.as_slice() {
+                [name] => format!("Revision {} is not found in {} repository.", rev, name),
+                _ => format!("Revision {} is not found in any repository.", rev),
+            }));
+        }
+        for name in &missing {
+            tracing::warn!(
+                "Revision {} is not found in {} repository, skipping it.",
+                rev,
+                name
+            );
+        }
+        self.config
+            .repos
+            .retain(|repo| !missing.contains(&repo.name));
+        self.revision = Some(Arc::new(Revision { commits, _dir: dir }));
+        Ok(self)
+    }
+
+    /// Commit the repository is read at, if it's read at a revision.
+    pub(super) fn revision_commit(&self, repo: &str) -> Option<&str> {
+        self.revision
+            .as_ref()
+            .and_then(|revision| revision.commits.get(repo))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::{PatternEntry, Repository},
+    errors::{IoContext, LdfmError, Result},
+    manager::{overlay::remove_overlays, resolve_path},
+    utils::{copy_tree, git_commit_paths, remove_path},
+};
+
+/// Result of [`Ldfm::track`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackOutcome {
+    /// Repository the file is tracked in.
+    pub repo: String,
+    /// Key of the entry in the repository config.
+    pub key: String,
+    /// Absolute path of the tracked file.
+    pub target: PathBuf,
+    /// Whether the file was tracked before.
+    pub already_tracked: bool,
+    /// Other repositories tracking the same file.
+    pub also_tracked_in: Vec<String>,
+}
+
+/// Result of [`Ldfm::untrack`].
+#[derive(Debug, Clone, Serialize)]
+pub struct UntrackOutcome {
+    /// Repository the file was untracked from.
+    pub repo: String,
+    /// Key the file was tracked with.
+    pub key: String,
+    /// Absolute path of the untracked file.
+    pub target: PathBuf,
+    /// Copy of the file removed from the repository, if there was one.
+    pub removed_copy: Option<PathBuf>,
+    /// Where the live file pointed to, if it was a symlink replaced with a real copy.
+    pub restored_from: Option<PathBuf>,
+    /// Whether the removal was committed.
+    pub committed: bool,
+}
+
+/// What to do besides removing the entry from the config, see [`Ldfm::untrack_with`].
+#[derive(Debug, Clone, Default)]
+pub struct UntrackOptions {
+    /// Untrack only the named block of the file. The block itself stays in the file.
+    pub block: Option<String>,
+    /// Leave the copy in the repository. It disappears on the next commit,
+    /// while its history stays in git.
+    pub keep_copy: bool,
+    /// Commit the removal of the copy.
+    pub commit: bool,
+    /// Replace the live file with a real copy if it's a symlink,
+    /// e.g. to the repository copy that is about to be removed.
+    pub restore: bool,
+}
+
+/// Kind of entry to track.
+#[derive(Clone, Copy)]
+enum Tracked<'a> {
+    File,
+    Pattern,
+    Block { block: &'a str, comment: &'a str },
+    Merge(&'a [String]),
+}
+
+impl Ldfm {
+    /// Start tracking a file or a directory in the primary repository.
+    ///
+    /// Paths with glob characters, like `~/.config/fish/functions/*.fish`,
+    /// are tracked as patterns that are expanded on every commit.
+    pub fn track(&self, path: &Path) -> Result<TrackOutcome> {
+        if PatternEntry::is_pattern(&path.to_string_lossy()) {
+            return self.track_entry(path, Tracked::Pattern);
+        }
+        self.track_entry(path, Tracked::File)
+    }
+
+    /// Start tracking a block of a file in the primary repository.
+    ///
+    /// The block is the text between `<comment> >>> ldfm:<block> >>>`
+    /// and `<comment> <<< ldfm:<block> <<<` lines, the rest of the file
+    /// is left to other tools.
+    pub fn track_block(&self, path: &Path, block: &str, comment: &str) -> Result<TrackOutcome> {
+        self.track_entry(path, Tracked::Block { block, comment })
+    }
+
+    /// Start tracking keys of a JSON, TOML, YAML or INI file in the primary repository.
+    ///
+    /// Keys are paths separated with `/`. `apply` merges them into the file
+    /// preserving all other keys.
+    pub fn track_merge(&self, path: &Path, keys: &[String]) -> Result<TrackOutcome> {
+        self.track_entry(path, Tracked::Merge(keys))
+    }
+
+    fn track_entry(&self, path: &Path, tracked: Tracked) -> Result<TrackOutcome> {
+        let repo = self.primary_repo()?;
+        let mut repo_config = repo.get_repo_config()?;
+        let target = resolve_path(path)?;
+        let block_name = match tracked {
+            Tracked::Block { block, .. } => Some(block),
+            Tracked::File | Tracked::Pattern | Tracked::Merge(_) => None,
+        };
+        let already_tracked = repo_config.find_key(&target, block_name)?.is_some();
+        let key = match tracked {
+            Tracked::File => repo_config.track_file(&target)?,
+            Tracked::Pattern => repo_config.track_pattern(&target)?,
+            Tracked::Block { block, comment } => {
+                repo_config.track_block(&target, block, comment)?
+            }
+            Tracked::Merge(keys) => repo_config.track_merge(&target, keys)?,
+        };
+        let mut also_tracked_in = Vec::new();
+        for other in self.config.repositories() {
+            if other.name != repo.name
+                && other
+                    .get_repo_config()?
+                    .find_key(&target, block_name)?
+                    .is_some()
+            {
+                also_tracked_in.push(other.name.clone());
+            }
+        }
+        repo.save_repo_config(&repo_config)?;
+        Ok(TrackOutcome {
+            repo: repo.name.clone(),
+            key,
+            target,
+            already_tracked,
+            also_tracked_in,
+        })
+    }
+
+    /// Stop tracking a file and remove its copy from the repository.
+    ///
+    /// Without a selected repository the file is looked up in all of them,
+    /// and it's a conflict if several repositories track it.
+    /// Returns `None` if the file isn't tracked.
+    pub fn untrack(&self, path: &Path) -> Result<Option<UntrackOutcome>> {
+        self.untrack_with(path, &UntrackOptions::default())
+    }
+
+    /// Stop tracking a block of a file, see [`Ldfm::untrack`].
+    ///
+    /// The block itself stays in the file.
+    pub fn untrack_block(&self, path: &Path, block: &str) -> Result<Option<UntrackOutcome>> {
+        self.untrack_with(
+            path,
+            &UntrackOptions {
+                block: Some(block.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Stop tracking a file, leaving both the live file and the repository copy in place.
+    pub fn forget(&self, path: &Path, block: Option<&str>) -> Result<Option<UntrackOutcome>> {
+        self.untrack_with(
+            path,
+            &UntrackOptions {
+                block: block.map(str::to_string),
+                keep_copy: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Stop tracking a file with the given options.
+    ///
+    /// The live file is only touched with `restore` set.
+    /// Returns `None` if the file isn't tracked.
+    pub fn untrack_with(
+        &self,
+        path: &Path,
+        options: &UntrackOptions,
+    ) -> Result<Option<UntrackOutcome>> {
+        let target = resolve_path(path)?;
+        let block = options.block.as_deref();
+        let Some(repo) = self.find_owner(&target, block)? else {
+            return Ok(None);
+        };
+        let mut repo_config = repo.get_repo_config()?;
+        let Some(key) = repo_config.untrack_file(&target, block)? else {
+            return Ok(None);
+        };
+        let restored_from = if options.restore {
+            restore_symlink(&target)?
+        } else {
+            None
+        };
+        let local_path = repo_config.get_local_path(&key);
+        let full_file_path = repo.local_path.join(&local_path);
+        let removed_copy = if options.keep_copy || !full_file_path.exists() {
+            None
+        } else {
+            remove_path(&full_file_path)?;
+            Some(full_file_path)
+        };
+        // Only commit what untracking changed, not other pending changes in the repository.
+        let mut changed = vec![PathBuf::from("ldfm.toml")];
+        if !options.keep_copy {
+            changed.push(local_path);
+            changed.extend(remove_overlays(repo, &key)?);
+        }
+        repo.save_repo_config(&repo_config)?;
+        let committed = options.commit
+            && git_commit_paths(
+                &repo.local_path.display().to_string(),
+                &format!("Untracked {}.", key),
+                &changed,
+            )?;
+        Ok(Some(UntrackOutcome {
+            repo: repo.name.clone(),
+            key,
+            target,
+            removed_copy,
+            restored_from,
+            committed,
+        }))
+    }
+
+    /// Find the repository tracking the target.
+    ///
+    /// It's a conflict if several selected repositories track it.
+    pub(super) fn find_owner(
+        &self,
+        target: &PathBuf,
+        block: Option<&str>,
+    ) -> Result<Option<&Repository>> {
+        let mut owners = Vec::new();
+        for repo in self.repositories() {
+            if repo.get_repo_config()?.find_key(target, block)?.is_some() {
+                owners.push(repo);
+            }
+        }
+        match owners.as_slice() {
+            [] => Ok(None),
+            [repo] => Ok(Some(*repo)),
+            _ => Err(LdfmError::Conflict(format!(
+                "File {} is tracked in several repositories: {}. Use `--repo` to choose one.",
+                target.display(),
+                owners
+                    .iter()
+                    .map(|repo| repo.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+/// Replace a symlink with a real copy of what it points to.
+///
+/// Returns the path the symlink pointed to, or `None` if the path isn't a symlink.
+fn restore_symlink(target: &Path) -> Result<Option<PathBuf>> {
+    if !target.is_symlink() {
+        return Ok(None);
+    }
+    let source = std::fs::canonicalize(target).with_path("resolve", target)?;
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = target.with_file_name(format!(".{}.ldfm-restore", name));
+    remove_path(&temp)?;
+    copy_tree(&source, &temp, &|_| false, &|_| {}, 1)?;
+    std::fs::remove_file(target).with_path("remove", target)?;
+    std::fs::rename(&temp, target).with_path("restore", target)?;
+    Ok(Some(source))
+}
@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    errors::{IoContext, LdfmError, Result},
+    utils::{copy_files, remove_path},
+};
+
+/// Files written by `apply`, staged next to their targets
+/// and moved into place only once all of them are ready.
+///
+/// Staged files that were never committed are removed on drop.
+#[derive(Debug, Default)]
+pub(super) struct Transaction {
+    staged: Vec<Staged>,
+    /// Directories created for staged files, parents first.
+    created_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct Staged {
+    target: PathBuf,
+    temp: PathBuf,
+}
+
+impl Transaction {
+    /// Stage copies of files given as pairs of sources and targets,
+    /// copying them on up to `jobs` threads.
+    ///
+    /// Returns the total size of the files.
+    pub fn stage_copies(
+        &mut self,
+        files: &[(PathBuf, PathBuf)],
+        copied: &(dyn Fn(u64) + Sync),
+        jobs: usize,
+    ) -> Result<u64> {
+        let mut copies = Vec::with_capacity(files.len());
+        for (source, target) in files {
+            copies.push((source.clone(), self.prepare(target)?));
+        }
+        copy_files(&copies, copied, jobs)
+    }
+
+    /// Stage new contents of the file, keeping permissions of the existing one.
+    pub fn stage_contents(&mut self, target: &Path, contents: &str) -> Result<()> {
+        let temp = self.prepare(target)?;
+        std::fs::write(&temp, contents).with_path("write", &temp)?;
+        let target = resolve(target);
+        if target.exists() {
+            let permissions = std::fs::metadata(&target)
+                .with_path("read", &target)?
+                .permissions();
+            std::fs::set_permissions(&temp, permissions).with_path("write", &temp)?;
+        }
+        Ok(())
+    }
+
+    /// Contents of the file including changes staged so far.
+    ///
+    /// Missing files are read as empty.
+    pub fn read(&self, target: &Path) -> Result<String> {
+        let target = resolve(target);
+        let path = match self.find(&target) {
+            Some(staged) => &staged.temp,
+            None => &target,
+        };
+        if !path.exists() {
+            return Ok(String::new());
+        }
+        std::fs::read_to_string(path).with_path("read", path)
+    }
+
+    /// Files that will be written, in the order they were staged.
+    pub fn targets(&self) -> Vec<PathBuf> {
+        self.staged
+            .iter()
+            .map(|staged| staged.target.clone())
+            .collect()
+    }
+
+    /// Directories created for staged files, parents first.
+    pub fn created_dirs(&self) -> &[PathBuf] {
+        &self.created_dirs
+    }
+
+    /// Whether the file was staged so far.
+    pub fn is_staged(&self, target: &Path) -> bool {
+        self.find(&resolve(target)).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// Move all staged files into place.
+    ///
+    /// If any of them can't be moved, files moved before it are restored.
+    pub fn commit(mut self) -> Result<()> {
+        let mut replaced = Vec::new();
+        let mut result = Ok(());
+        for staged in &self.staged {
+            match replace(staged) {
+                Ok(backup) => replaced.push((staged, backup)),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        if result.is_err() {
+            for (staged, backup) in replaced.iter().rev() {
+                let restored = match backup {
+                    Some(backup) => std::fs::rename(backup, &staged.target),
+                    None => std::fs::remove_file(&staged.target),
+                };
+                if let Err(err) = restored {
+                    tracing::error!("Cannot restore {}: {}", staged.target.display(), err);
+                }
+            }
+            return result;
+        }
+        for (_, backup) in replaced {
+            if let Some(backup) = backup {
+                remove_path(&backup)?;
+            }
+        }
+        self.staged.clear();
+        self.created_dirs.clear();
+        Ok(())
+    }
+
+    fn find(&self, target: &Path) -> Option<&Staged> {
+        self.staged.iter().find(|staged| staged.target == target)
+    }
+
+    /// Check that the target can be written and return the path to stage it at.
+    fn prepare(&mut self, target: &Path) -> Result<PathBuf> {
+        let target = resolve(target);
+        if let Some(staged) = self.find(&target) {
+            return Ok(staged.temp.clone());
+        }
+        if target.is_dir() {
+            return Err(LdfmError::Conflict(format!(
+                "Cannot write file {}, it's a directory.",
+                target.display()
+            )));
+        }
+        let (Some(parent), Some(name)) = (target.parent(), target.file_name()) else {
+            return Err(LdfmError::Validation(format!(
+                "Cannot write file {}.",
+                target.display()
+            )));
+        };
+        let missing = parent
+            .ancestors()
+            .take_while(|dir| !dir.exists())
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        std::fs::create_dir_all(parent).with_path("create", parent)?;
+        self.created_dirs.extend(missing.into_iter().rev());
+        let temp = parent.join(format!(".{}.ldfm-new", name.to_string_lossy()));
+        self.staged.push(Staged {
+            target,
+            temp: temp.clone(),
+        });
+        Ok(temp)
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        for staged in &self.staged {
+            if let Err(err) = remove_path(&staged.temp) {
+                tracing::warn!("{}", err);
+            }
+        }
+        for dir in self.created_dirs.iter().rev() {
+            // Fails if the directory isn't empty, which is fine.
+            std::fs::remove_dir(dir).ok();
+        }
+    }
+}
+
+/// Write through symlinks instead of replacing them.
+fn resolve(target: &Path) -> PathBuf {
+    if target.is_symlink() {
+        std::fs::canonicalize(target).unwrap_or(target.to_path_buf())
+    } else {
+        target.to_path_buf()
+    }
+}
+
+/// Atomically replace the target with the staged file.
+///
+/// Returns the path of the previous version of the target, if it existed.
+fn replace(staged: &Staged) -> Result<Option<PathBuf>> {
+    let target = &staged.target;
+    let backup = if target.exists() {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let backup = target.with_file_name(format!(".{}.ldfm-old", name));
+        remove_path(&backup)?;
+        if std::fs::hard_link(target, &backup).is_err() {
+            std::fs::copy(target, &backup).with_path("back up", target)?;
+        }
+        Some(backup)
+    } else {
+        None
+    };
+    if let Err(err) = std::fs::rename(&staged.temp, target) {
+        if let Some(backup) = &backup {
+            std::fs::remove_file(backup).ok();
+        }
+        return Err(LdfmError::io(
+            format!("Cannot write {}", target.display()),
+            err,
+        ));
+    }
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transaction;
+
+    #[test]
+    fn failed_commit_restores_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("nested/second");
+        std::fs::write(&first, "old").unwrap();
+
+        let mut transaction = Transaction::default();
+        transaction.stage_contents(&first, "new").unwrap();
+        transaction.stage_contents(&second, "new").unwrap();
+        assert_eq!(transaction.read(&first).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "old");
+        // Break the second file, so it can't be moved into place.
+        std::fs::remove_file(&transaction.staged[1].temp).unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "old");
+        let names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["first"]);
+    }
+
+    #[test]
+    fn commit_replaces_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        std::fs::write(&first, "old").unwrap();
+        let mut transaction = Transaction::default();
+        transaction.stage_contents(&first, "new").unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "new");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stage_copies_in_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = (0..20)
+            .map(|i| {
+                let source = dir.path().join(format!("source/{i}"));
+                std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+                std::fs::write(&source, "x".repeat(i)).unwrap();
+                (source, dir.path().join(format!("target/nested/{i}")))
+            })
+            .collect::<Vec<_>>();
+
+        let mut transaction = Transaction::default();
+        let bytes = transaction.stage_copies(&files, &|_| {}, 4).unwrap();
+        assert_eq!(bytes, (0..20).sum::<usize>() as u64);
+        assert_eq!(
+            transaction.targets(),
+            files
+                .iter()
+                .map(|(_, target)| target.clone())
+                .collect::<Vec<_>>()
+        );
+        transaction.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&files[7].1).unwrap(), "xxxxxxx");
+
+        // The error of the first failed file is reported.
+        let mut broken = files.clone();
+        broken[3].0 = dir.path().join("missing-3");
+        broken[15].0 = dir.path().join("missing-15");
+        let mut transaction = Transaction::default();
+        let err = transaction.stage_copies(&broken, &|_| {}, 4).unwrap_err();
+        assert!(err.to_string().contains("missing-3"), "{err}");
+    }
+}
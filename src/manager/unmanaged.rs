@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    Ldfm,
+    configs::{Entry, UnmanagedConfig},
+    errors::{LdfmError, Result},
+    excludes::Excludes,
+    manager::resolve_path,
+    report::EntryType,
+    utils::home_dir,
+};
+
+/// A file or directory not covered by any tracked entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmanagedEntry {
+    /// Path starting with `~` if it's inside the home directory.
+    pub path: String,
+    pub entry_type: EntryType,
+}
+
+/// Paths that are skipped or descended into while scanning.
+struct Scan {
+    home: PathBuf,
+    /// Tracked paths and repositories themselves.
+    covered: Vec<PathBuf>,
+    roots: Vec<PathBuf>,
+    ignore: Excludes,
+}
+
+impl Ldfm {
+    /// Find files under the configured roots that no entry tracks.
+    ///
+    /// Directories are reported as a whole once they reach the depth limit,
+    /// except parents of tracked entries, which are always looked into,
+    /// so new files next to tracked ones are found. At the top of the home
+    /// directory only hidden files are considered.
+    pub fn unmanaged(&self, options: &UnmanagedConfig) -> Result<Vec<UnmanagedEntry>> {
+        let home = home_dir().ok_or(LdfmError::Validation(
+            "Cannot get home directory. Set HOME or LDFM_HOME.".to_string(),
+        ))?;
+        let mut covered = Vec::new();
+        for repo in self.repositories() {
+            let repo_config = repo.get_repo_config()?;
+            for (key, entry) in &repo_config.files {
+                let live_path = repo_config.live_path(key)?;
+                match entry {
+                    Entry::Pattern(pattern) => covered.extend(
+                        pattern
+                            .expand()?
+                            .into_iter()
+                            .map(|file| live_path.join(file)),
+                    ),
+                    _ => covered.push(live_path),
+                }
+            }
+        }
+        for repo in &self.config.repos {
+            covered.push(resolve_path(&repo.local_path)?);
+        }
+        let scan = Scan {
+            home,
+            covered,
+            roots: options
+                .roots
+                .iter()
+                .map(|root| resolve_path(Path::new(root)))
+                .collect::<Result<_>>()?,
+            ignore: Excludes::new(&options.ignore)?,
+        };
+        let mut found = BTreeSet::new();
+        for root in &scan.roots {
+            if root.is_dir() {
+                scan.dir(root, options.depth.max(1), &mut found);
+            }
+        }
+        Ok(found
+            .into_iter()
+            .map(|path| UnmanagedEntry {
+                entry_type: if path.is_dir() {
+                    EntryType::Directory
+                } else {
+                    EntryType::File
+                },
+                path: match path.strip_prefix(&scan.home) {
+                    Ok(relative) => format!("~/{}", relative.display()),
+                    Err(_) => path.display().to_string(),
+                },
+            })
+            .collect())
+    }
+}
+
+impl Scan {
+    fn dir(&self, dir: &Path, depth: usize, found: &mut BTreeSet<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::debug!("Cannot read {}: {}", dir.display(), err);
+                return;
+            }
+        };
+        let mut names = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let path = dir.join(&name);
+            if dir == self.home && !name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let relative = path.strip_prefix(&self.home).unwrap_or(&path);
+            if self.ignore.is_excluded(relative)
+                || self.covered.iter().any(|covered| path.starts_with(covered))
+                || self.roots.iter().any(|root| root.starts_with(&path))
+            {
+                continue;
+            }
+            let is_dir = path.is_dir();
+            if is_dir
+                && self
+                    .covered
+                    .iter()
+                    .any(|covered| covered.starts_with(&path))
+            {
+                self.dir(&path, depth, found);
+            } else if is_dir && depth > 1 {
+                self.dir(&path, depth - 1, found);
+            } else {
+                found.insert(path);
+            }
+        }
+    }
+}
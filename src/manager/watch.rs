@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use globset::GlobMatcher;
+
+use crate::{Ldfm, configs::Entry, errors::Result, excludes::Excludes};
+
+/// A tracked entry to watch for changes.
+#[derive(Debug, Clone)]
+pub struct WatchTarget {
+    pub repo: String,
+    pub key: String,
+    /// Tracked path on this machine.
+    pub path: PathBuf,
+    excludes: Excludes,
+    /// Pattern of the entry relative to `path`, if it's a pattern.
+    pattern: Option<GlobMatcher>,
+}
+
+impl WatchTarget {
+    /// Check whether a change of the path affects this entry.
+    ///
+    /// Changes of excluded files inside tracked directories
+    /// and of files not matching the pattern of the entry are ignored.
+    pub fn matches(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.path) {
+            Ok(relative) => {
+                !self.excludes.is_excluded(relative)
+                    && self
+                        .pattern
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.is_match(relative))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl Ldfm {
+    /// Tracked entries of all repositories along with their exclude patterns.
+    pub fn watch_targets(&self) -> Result<Vec<WatchTarget>> {
+        let mut targets = Vec::new();
+        for repo in self.repositories() {
+            let repo_config = repo.get_repo_config()?;
+            let excludes = Excludes::new(&repo_config.exclude)?;
+            let mut keys = repo_config.files.keys().collect::<Vec<_>>();
+            keys.sort();
+            for key in keys {
+                let path = repo_config.live_path(key)?;
+                targets.push(WatchTarget {
+                    repo: repo.name.clone(),
+                    key: key.clone(),
+                    path,
+                    excludes: excludes.clone(),
+                    pattern: match &repo_config.files[key] {
+                        Entry::Pattern(pattern) => Some(pattern.matcher()?),
+                        _ => None,
+                    },
+                });
+            }
+        }
+        Ok(targets)
+    }
+}
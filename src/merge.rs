@@ -0,0 +1,236 @@
+//! Structured files used by merge entries.
+//!
+//! All formats are converted to a JSON value, so managed keys can be
+//! extracted and merged the same way regardless of the format.
+//! Files are written back from the value, which loses comments and formatting,
+//! so files with comments are not merged, see [`has_comments`].
+use serde_json::{Map, Value};
+
+use crate::configs::MergeFormat;
+
+pub fn parse(format: MergeFormat, contents: &str) -> Result<Value, String> {
+    if contents.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    match format {
+        MergeFormat::Json => serde_json::from_str(contents).map_err(|err| err.to_string()),
+        MergeFormat::Toml => toml::from_str(contents).map_err(|err| err.to_string()),
+        MergeFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| err.to_string()),
+        MergeFormat::Ini => parse_ini(contents),
+    }
+}
+
+pub fn serialize(format: MergeFormat, value: &Value) -> Result<String, String> {
+    match format {
+        MergeFormat::Json => serde_json::to_string_pretty(value)
+            .map(|json| json + "\n")
+            .map_err(|err| err.to_string()),
+        MergeFormat::Toml => toml::to_string_pretty(value).map_err(|err| err.to_string()),
+        MergeFormat::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string()),
+        MergeFormat::Ini => Ok(serialize_ini(value)),
+    }
+}
+
+/// Whether the file has comments, which merging would drop.
+///
+/// Comment markers inside quoted strings are ignored.
+pub fn has_comments(format: MergeFormat, contents: &str) -> bool {
+    contents.lines().any(|line| {
+        let trimmed = line.trim_start();
+        let starts_comment = match format {
+            MergeFormat::Json => |rest: &str, _| rest.starts_with("//") || rest.starts_with("/*"),
+            MergeFormat::Toml => |rest: &str, _| rest.starts_with('#'),
+            // YAML only starts comments after whitespace, `a#b` is a plain value.
+            MergeFormat::Yaml => |rest: &str, after_space| after_space && rest.starts_with('#'),
+            MergeFormat::Ini => {
+                return trimmed.starts_with([';', '#']);
+            }
+        };
+        let mut quote = None;
+        let mut escaped = false;
+        let mut after_space = true;
+        for (i, char) in trimmed.char_indices() {
+            match quote {
+                Some(_) if escaped => escaped = false,
+                Some('"') if char == '\\' => escaped = true,
+                Some(open) if char == open => quote = None,
+                Some(_) => {}
+                None if char == '"' || (char == '\'' && format != MergeFormat::Json) => {
+                    quote = Some(char);
+                }
+                None if starts_comment(&trimmed[i..], after_space) => return true,
+                None => {}
+            }
+            after_space = char.is_whitespace();
+        }
+        false
+    })
+}
+
+/// Copy only the given key paths of the value.
+///
+/// Missing keys are skipped.
+pub fn extract(value: &Value, keys: &[String]) -> Value {
+    let mut result = Value::Object(Map::new());
+    for key in keys {
+        let path = key.split('/').collect::<Vec<_>>();
+        let Some(found) = path
+            .iter()
+            .try_fold(value, |value, segment| value.get(segment))
+        else {
+            continue;
+        };
+        let mut target = &mut result;
+        for segment in &path[..path.len() - 1] {
+            target = target
+                .as_object_mut()
+                .expect("intermediate values are objects")
+                .entry(segment.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+        }
+        target
+            .as_object_mut()
+            .expect("intermediate values are objects")
+            .insert(path[path.len() - 1].to_string(), found.clone());
+    }
+    result
+}
+
+/// Deep merge the managed value into the live one.
+///
+/// Objects are merged key by key, any other values are replaced.
+pub fn merge(live: &mut Value, managed: &Value) {
+    match (live, managed) {
+        (Value::Object(live), Value::Object(managed)) => {
+            for (key, value) in managed {
+                match live.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        live.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (live, managed) => *live = managed.clone(),
+    }
+}
+
+/// INI files are objects of sections, keys outside of sections are top-level strings.
+fn parse_ini(contents: &str) -> Result<Value, String> {
+    let ini = ini::Ini::load_from_str(contents).map_err(|err| err.to_string())?;
+    let mut result = Map::new();
+    for (section, properties) in ini.iter() {
+        let mut values = Map::new();
+        for (key, value) in properties.iter() {
+            values.insert(key.to_string(), Value::String(value.to_string()));
+        }
+        match section {
+            Some(section) => {
+                result.insert(section.to_string(), Value::Object(values));
+            }
+            None => result.extend(values),
+        }
+    }
+    Ok(Value::Object(result))
+}
+
+fn serialize_ini(value: &Value) -> String {
+    let as_string = |value: &Value| match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    let mut ini = ini::Ini::new();
+    for (key, value) in value.as_object().into_iter().flatten() {
+        match value {
+            Value::Object(properties) => {
+                for (name, value) in properties {
+                    ini.with_section(Some(key.as_str()))
+                        .set(name.as_str(), as_string(value));
+                }
+            }
+            value => {
+                ini.with_general_section()
+                    .set(key.as_str(), as_string(value));
+            }
+        }
+    }
+    let mut output = Vec::new();
+    ini.write_to(&mut output)
+        .expect("writing to a vector never fails");
+    String::from_utf8_lossy(&output).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{extract, has_comments, merge, parse, serialize};
+    use crate::configs::MergeFormat;
+
+    #[test]
+    fn extract_and_merge() {
+        let live = json!({
+            "editor.fontSize": 12,
+            "window.zoomLevel": 1,
+            "aliases": {"co": "checkout", "st": "status"},
+        });
+        let keys = ["editor.fontSize".to_string(), "aliases/co".to_string()];
+        let managed = extract(&live, &keys);
+        assert_eq!(
+            managed,
+            json!({"editor.fontSize": 12, "aliases": {"co": "checkout"}})
+        );
+
+        let mut other = json!({"window.zoomLevel": 2, "aliases": {"st": "stash"}});
+        merge(&mut other, &managed);
+        assert_eq!(
+            other,
+            json!({
+                "window.zoomLevel": 2,
+                "aliases": {"st": "stash", "co": "checkout"},
+                "editor.fontSize": 12,
+            })
+        );
+    }
+
+    #[test]
+    fn detect_comments() {
+        let cases = [
+            (MergeFormat::Json, "{\"a\": 1} // note", true),
+            (MergeFormat::Json, "{\n  /* note */\n  \"a\": 1\n}", true),
+            (
+                MergeFormat::Json,
+                "{\"url\": \"https://example.com\"}",
+                false,
+            ),
+            (MergeFormat::Toml, "# note\na = 1", true),
+            (MergeFormat::Toml, "a = 1 # note", true),
+            (MergeFormat::Toml, "color = \"#fff\"\nb = 'x#y'", false),
+            (MergeFormat::Yaml, "a: 1 # note", true),
+            (MergeFormat::Yaml, "  # note\na: 1", true),
+            (MergeFormat::Yaml, "a: b#c\nd: \"# not a comment\"", false),
+            (MergeFormat::Ini, "; note\n[a]\nb=1", true),
+            (MergeFormat::Ini, "[a]\ncolor=#fff", false),
+        ];
+        for (format, contents, expected) in cases {
+            assert_eq!(has_comments(format, contents), expected, "{contents}");
+        }
+    }
+
+    #[test]
+    fn formats_roundtrip() {
+        let cases = [
+            (
+                MergeFormat::Json,
+                "{\n  \"a\": 1,\n  \"b\": {\n    \"c\": true\n  }\n}\n",
+            ),
+            (MergeFormat::Toml, "a = 1\n\n[b]\nc = true\n"),
+            (MergeFormat::Yaml, "a: 1\nb:\n  c: true\n"),
+            (MergeFormat::Ini, "a=1\n\n[b]\nc=true\n"),
+        ];
+        for (format, contents) in cases {
+            let value = parse(format, contents).unwrap();
+            assert_eq!(serialize(format, &value).unwrap(), contents, "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Rendering of command results in the format selected with `--format`.
+use std::{
+    io::{IsTerminal, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use ldfm::{
+    HistoryEntry, Ldfm, UnmanagedEntry,
+    progress::Progress,
+    report::{EntryReport, SyncOutcome, SyncStatus},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// One record per line, meant for humans and simple shell pipelines.
+    #[default]
+    Plain,
+    /// Aligned columns with a header.
+    Table,
+    /// A JSON array of records.
+    Json,
+}
+
+/// A record that can be printed by reporting commands.
+pub trait Record: Serialize {
+    /// Column names of the table format.
+    fn columns() -> &'static [&'static str];
+    /// Values of the table columns.
+    fn row(&self) -> Vec<String>;
+    /// Single line of the plain format.
+    fn plain(&self) -> String;
+}
+
+pub fn print<R: Record>(format: OutputFormat, records: &[R]) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        OutputFormat::Plain => {
+            for record in records {
+                writeln!(stdout, "{}", record.plain())?;
+            }
+        }
+        OutputFormat::Table => {
+            let rows = records.iter().map(R::row).collect::<Vec<_>>();
+            let mut widths = R::columns().iter().map(|c| c.len()).collect::<Vec<_>>();
+            for row in &rows {
+                for (width, value) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(value.chars().count());
+                }
+            }
+            let header = R::columns()
+                .iter()
+                .map(|column| column.to_uppercase())
+                .collect::<Vec<_>>();
+            for row in std::iter::once(&header).chain(&rows) {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{:<width$}", value, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                writeln!(stdout, "{}", line.trim_end())?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, records)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+impl Record for EntryReport {
+    fn columns() -> &'static [&'static str] {
+        &["repo", "key", "type", "target", "live", "in repo", "state"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+        vec![
+            self.repo.clone(),
+            self.key.clone(),
+            self.entry_type.to_string(),
+            self.target.clone(),
+            yes_no(self.live_exists),
+            yes_no(self.repo_exists),
+            self.state
+                .map(|state| state.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let target = match &self.block {
+            Some(block) => format!("{} ({})", self.target, block),
+            None => self.target.clone(),
+        };
+        let line = match self.state {
+            Some(state) => format!("{:<12} {}", state.to_string(), target),
+            None => target,
+        };
+        match &self.error {
+            Some(error) => format!("{}: {}", line, error),
+            None => line,
+        }
+    }
+}
+
+impl Record for UnmanagedEntry {
+    fn columns() -> &'static [&'static str] {
+        &["path", "type"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.path.clone(), self.entry_type.to_string()]
+    }
+
+    fn plain(&self) -> String {
+        self.path.clone()
+    }
+}
+
+impl Record for HistoryEntry {
+    fn columns() -> &'static [&'static str] {
+        &["id", "time", "commits", "files"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let commits = self
+            .commits
+            .iter()
+            .map(|applied| {
+                let commit = applied.commit.as_deref().unwrap_or("none");
+                format!("{}@{}", applied.repo, &commit[..commit.len().min(7)])
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        vec![
+            self.id.to_string(),
+            humantime::format_rfc3339_seconds(
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(self.timestamp),
+            )
+            .to_string(),
+            commits,
+            self.files.len().to_string(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let row = self.row();
+        format!("{:<4} {}  {}  {} files", row[0], row[1], row[2], row[3])
+    }
+}
+
+impl Record for SyncOutcome {
+    fn columns() -> &'static [&'static str] {
+        &["repo", "key", "status", "size", "reason"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.repo.clone(),
+            self.key.clone(),
+            self.status.to_string(),
+            format_bytes(self.bytes),
+            self.reason.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let line = format!(
+            "{:<10} {}: {}",
+            self.status.to_string(),
+            self.repo,
+            self.key
+        );
+        match &self.reason {
+            Some(reason) => format!("{} ({})", line, reason),
+            None => line,
+        }
+    }
+}
+
+/// Print outcomes of `apply` or `commit` and log how many entries ended up in each state.
+pub fn print_summary(
+    operation: &str,
+    format: OutputFormat,
+    outcomes: &[SyncOutcome],
+) -> anyhow::Result<()> {
+    print(format, outcomes)?;
+    let count = |status| {
+        outcomes
+            .iter()
+            .filter(|outcome| outcome.status == status)
+            .count()
+    };
+    tracing::info!(
+        "{}: {} created, {} updated, {} unchanged, {} skipped, {} failed, {} written.",
+        operation,
+        count(SyncStatus::Created),
+        count(SyncStatus::Updated),
+        count(SyncStatus::Unchanged),
+        count(SyncStatus::Skipped),
+        count(SyncStatus::Failed),
+        format_bytes(outcomes.iter().map(|outcome| outcome.bytes).sum()),
+    );
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Whether the progress line is currently drawn on stderr.
+static PROGRESS_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Writer of log lines, which clears the progress line first.
+pub fn log_writer() -> std::io::Stderr {
+    clear_progress();
+    std::io::stderr()
+}
+
+fn clear_progress() {
+    if PROGRESS_SHOWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[2K");
+    }
+}
+
+/// Show progress of `apply` and `commit` if stderr is a terminal.
+pub fn with_progress(ldfm: Ldfm) -> Ldfm {
+    if std::io::stderr().is_terminal() {
+        ldfm.with_progress(TerminalProgress::default())
+    } else {
+        ldfm
+    }
+}
+
+/// A single progress line on stderr, redrawn in place.
+#[derive(Debug, Default)]
+struct TerminalProgress {
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Debug, Default)]
+struct ProgressState {
+    total: usize,
+    done: usize,
+    bytes: u64,
+    current: String,
+    drawn_at: Option<Instant>,
+}
+
+impl ProgressState {
+    /// Redraw the line, at most every 100ms.
+    fn draw(&mut self) {
+        let now = Instant::now();
+        if self
+            .drawn_at
+            .is_some_and(|drawn_at| now - drawn_at < Duration::from_millis(100))
+        {
+            return;
+        }
+        self.drawn_at = Some(now);
+        let current = self.current.chars().take(60).collect::<String>();
+        eprint!(
+            "\r\x1b[2K[{}/{}] {}  {}",
+            self.done,
+            self.total,
+            format_bytes(self.bytes),
+            current
+        );
+        PROGRESS_SHOWN.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn start(&self, entries: usize) {
+        let mut state = self.state.lock().unwrap();
+        *state = ProgressState {
+            total: entries,
+            ..Default::default()
+        };
+    }
+
+    fn entry(&self, repo: &str, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.current = format!("{}: {}", repo, key);
+        state.draw();
+    }
+
+    fn copied(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes += bytes;
+        state.draw();
+    }
+
+    fn entry_done(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.done += 1;
+        state.draw();
+    }
+
+    fn finish(&self) {
+        clear_progress();
+    }
+}
@@ -0,0 +1,24 @@
+//! Progress of long-running operations like `apply` and `commit`.
+
+/// Receives progress events, e.g. to display them.
+///
+/// Set it with [`Ldfm::with_progress`](crate::Ldfm::with_progress).
+/// All methods do nothing by default.
+pub trait Progress: Send + Sync + std::fmt::Debug {
+    /// An operation over the given number of entries started.
+    fn start(&self, _entries: usize) {}
+    /// Processing of the next entry started.
+    fn entry(&self, _repo: &str, _key: &str) {}
+    /// A file of the current entry was written.
+    fn copied(&self, _bytes: u64) {}
+    /// Processing of the current entry finished, successfully or not.
+    fn entry_done(&self) {}
+    /// The operation finished, successfully or not.
+    fn finish(&self) {}
+}
+
+/// Progress that is not reported anywhere.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
@@ -0,0 +1,307 @@
+//! Machine-readable reports about tracked entries.
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    configs::{Entry, RepoConfig, Repository},
+    errors::{IoContext, LdfmError, Result},
+    utils::{expand_tilde, walk_dir},
+};
+
+/// Information about a single tracked entry.
+///
+/// It's the common schema of all reporting commands.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryReport {
+    /// Name of the repository tracking the entry.
+    pub repo: String,
+    /// Key of the entry in the repository config.
+    pub key: String,
+    /// Path of the entry's copy inside the repository.
+    pub repo_path: PathBuf,
+    /// Tracked path as written in the repository config.
+    pub target: String,
+    pub entry_type: EntryType,
+    /// Whether the tracked path exists on this machine.
+    pub live_exists: bool,
+    /// Whether the copy exists in the repository.
+    pub repo_exists: bool,
+    /// Name of the managed block, if the entry is a block of the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    /// Sync state of the entry. Only reported by `status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<EntryState>,
+    /// Why the entry couldn't be inspected, e.g. an unparsable merged file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    entry: Entry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+    File,
+    Directory,
+    /// A managed block inside a file.
+    Block,
+    /// Managed keys of a structured config file.
+    Merge,
+    /// Files matching a glob pattern.
+    Pattern,
+    /// Neither the tracked path nor the repository copy exist.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryState {
+    /// The tracked path and the repository copy are the same.
+    Synced,
+    /// The tracked path differs from the repository copy.
+    Modified,
+    /// The entry is only in the repository and will be created by `apply`.
+    MissingLive,
+    /// The entry was never committed to the repository.
+    MissingRepo,
+    /// The entry exists neither on this machine nor in the repository.
+    Missing,
+    /// The entry couldn't be inspected, see [`EntryReport::error`].
+    Error,
+}
+
+/// What `apply` or `commit` did with a single entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncOutcome {
+    pub repo: String,
+    pub key: String,
+    pub status: SyncStatus,
+    /// Size of the files written for the entry.
+    pub bytes: u64,
+    /// Why the entry was skipped or failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    /// The entry didn't exist at the destination before.
+    Created,
+    /// The destination had different contents.
+    Updated,
+    /// The destination already had the same contents.
+    Unchanged,
+    /// There was nothing to copy or the entry wasn't written because of other failures.
+    Skipped,
+    Failed,
+}
+
+impl SyncOutcome {
+    pub fn new(repo: &str, key: &str, status: SyncStatus) -> Self {
+        Self {
+            repo: repo.to_string(),
+            key: key.to_string(),
+            status,
+            bytes: 0,
+            reason: None,
+        }
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+impl std::fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Unchanged => "unchanged",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        };
+        f.write_str(status)
+    }
+}
+
+impl std::fmt::Display for EntryState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            Self::Synced => "synced",
+            Self::Modified => "modified",
+            Self::MissingLive => "missing-live",
+            Self::MissingRepo => "missing-repo",
+            Self::Missing => "missing",
+            Self::Error => "error",
+        };
+        f.write_str(state)
+    }
+}
+
+impl std::fmt::Display for EntryType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entry_type = match self {
+            Self::File => "file",
+            Self::Directory => "directory",
+            Self::Block => "block",
+            Self::Merge => "merge",
+            Self::Pattern => "pattern",
+            Self::Unknown => "unknown",
+        };
+        f.write_str(entry_type)
+    }
+}
+
+impl EntryReport {
+    /// Describe all entries of the repository, sorted by key.
+    ///
+    /// An entry that can't be inspected is reported with an error
+    /// instead of failing the whole report.
+    pub fn collect(repo: &Repository, repo_config: &RepoConfig) -> Result<Vec<Self>> {
+        let mut keys = repo_config.files.keys().collect::<Vec<_>>();
+        keys.sort();
+        let mut reports = Vec::with_capacity(keys.len());
+        for key in keys {
+            let entry = &repo_config.files[key];
+            let repo_path = repo
+                .local_path
+                .join(repo_config.host_path(&repo.local_path, key));
+            let inspected = repo_config.live_path(key).and_then(|live_path| {
+                let live_exists = match (entry, entry.partial()) {
+                    (_, Some(partial)) => partial.read_live()?.is_some(),
+                    (Entry::Pattern(pattern), None) => !pattern.expand()?.is_empty(),
+                    (_, None) => live_path.exists(),
+                };
+                Ok((live_path, live_exists))
+            });
+            let (live_path, live_exists, error) = match inspected {
+                Ok((live_path, live_exists)) => (live_path, live_exists, None),
+                Err(error) => (PathBuf::new(), false, Some(error.to_string())),
+            };
+            let entry_type = match entry {
+                Entry::Block(_) => EntryType::Block,
+                Entry::Merge(_) => EntryType::Merge,
+                Entry::Pattern(_) => EntryType::Pattern,
+                Entry::Path(_)
+                    if live_path.is_dir() || (!live_path.exists() && repo_path.is_dir()) =>
+                {
+                    EntryType::Directory
+                }
+                Entry::Path(_) if live_path.exists() || repo_path.exists() => EntryType::File,
+                Entry::Path(_) => EntryType::Unknown,
+            };
+            reports.push(Self {
+                repo: repo.name.clone(),
+                key: key.clone(),
+                live_exists,
+                repo_exists: repo_path.exists(),
+                repo_path,
+                target: entry.path().to_string(),
+                entry_type,
+                block: entry.block().map(str::to_string),
+                state: None,
+                error,
+                entry: entry.clone(),
+            });
+        }
+        Ok(reports)
+    }
+
+    /// Compare the tracked path with the repository copy and fill in the state.
+    ///
+    /// If they can't be compared, the state is [`EntryState::Error`].
+    pub fn with_state(mut self) -> Self {
+        if self.error.is_none()
+            && let Err(error) = self.compare()
+        {
+            self.error = Some(error.to_string());
+        }
+        if self.error.is_some() {
+            self.state = Some(EntryState::Error);
+        }
+        self
+    }
+
+    fn compare(&mut self) -> Result<()> {
+        let synced = match (&self.entry, self.entry.partial()) {
+            _ if !(self.live_exists && self.repo_exists) => false,
+            (Entry::Pattern(pattern), _) => {
+                let base = pattern.base()?;
+                let matches = pattern.expand()?;
+                let mut synced = matches == walk_dir(&self.repo_path, true)?;
+                for file in &matches {
+                    if !synced {
+                        break;
+                    }
+                    synced = same_contents(&base.join(file), &self.repo_path.join(file))?;
+                }
+                synced
+            }
+            (_, Some(partial)) => {
+                partial.read_live()?
+                    == Some(
+                        std::fs::read_to_string(&self.repo_path)
+                            .with_path("read", &self.repo_path)?,
+                    )
+            }
+            (_, None) => {
+                let live_path = expand_tilde(&self.target).ok_or(LdfmError::Validation(
+                    "Cannot get home directory. Set HOME or LDFM_HOME.".to_string(),
+                ))?;
+                same_contents(&live_path, &self.repo_path)?
+            }
+        };
+        self.state = Some(match (self.live_exists, self.repo_exists) {
+            (true, true) if synced => EntryState::Synced,
+            (true, true) => EntryState::Modified,
+            (false, true) => EntryState::MissingLive,
+            (true, false) => EntryState::MissingRepo,
+            (false, false) => EntryState::Missing,
+        });
+        Ok(())
+    }
+}
+
+/// Check whether two files or directory trees have the same contents.
+pub fn same_contents(left: &Path, right: &Path) -> Result<bool> {
+    match (left.is_dir(), right.is_dir()) {
+        (true, true) => {
+            let mut left_entries = read_dir_names(left)?;
+            let mut right_entries = read_dir_names(right)?;
+            left_entries.sort();
+            right_entries.sort();
+            if left_entries != right_entries {
+                return Ok(false);
+            }
+            for name in left_entries {
+                if !same_contents(&left.join(&name), &right.join(&name))? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (false, false) => {
+            let left_meta = std::fs::metadata(left).with_path("read", left)?;
+            let right_meta = std::fs::metadata(right).with_path("read", right)?;
+            if left_meta.len() != right_meta.len() {
+                return Ok(false);
+            }
+            Ok(std::fs::read(left).with_path("read", left)?
+                == std::fs::read(right).with_path("read", right)?)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn read_dir_names(path: &Path) -> Result<Vec<std::ffi::OsString>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(path).with_path("read", path)? {
+        names.push(entry.with_path("read", path)?.file_name());
+    }
+    Ok(names)
+}
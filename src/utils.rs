@@ -2,54 +2,337 @@ use std::{
     ffi::OsStr,
     fs::File,
     io::{BufRead, BufReader},
-    path::PathBuf,
-    process::ExitStatus,
+    path::{Component, Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
-pub fn run_command<T: AsRef<OsStr>>(
-    command: impl AsRef<OsStr>,
-    args: impl IntoIterator<Item = T>,
-) -> anyhow::Result<ExitStatus> {
-    let status = std::process::Command::new(command)
-        .args(args)
-        .spawn()?
-        .wait()?;
-    Ok(status)
-}
-
-pub fn git_clone(repo_url: &str, local_path: &str) -> anyhow::Result<()> {
-    let clone_code = run_command("git", ["clone", repo_url, local_path])?;
-    if !clone_code.success() {
-        anyhow::bail!(
-            "Failed to clone repository from {} to {}: {:?}",
-            repo_url,
-            local_path,
-            clone_code
-        );
+use serde::Serialize;
+
+use crate::errors::{IoContext, LdfmError, Result};
+
+/// Home directory used to resolve tracked paths.
+///
+/// Can be overridden with the `LDFM_HOME` environment variable.
+pub fn home_dir() -> Option<PathBuf> {
+    match std::env::var_os("LDFM_HOME") {
+        Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => std::env::home_dir(),
+    }
+}
+
+/// Directory with local state of ldfm, like the apply history.
+///
+/// It's `$XDG_STATE_HOME/ldfm` or `~/.local/state/ldfm` by default
+/// and can be overridden with the `LDFM_STATE_DIR` environment variable.
+pub fn state_dir() -> Option<PathBuf> {
+    match std::env::var_os("LDFM_STATE_DIR") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("ldfm")),
+    }
+}
+
+/// Name of this machine used for host-specific copies of entries.
+///
+/// Can be overridden with the `LDFM_HOST` environment variable.
+pub fn hostname() -> Option<String> {
+    if let Some(host) = std::env::var_os("LDFM_HOST").filter(|host| !host.is_empty()) {
+        return Some(host.to_string_lossy().to_string());
+    }
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|host| host.trim().to_string())
+        .find(|host| !host.is_empty())
+}
+
+/// Replace the leading `~` of the path with the home directory.
+pub fn expand_tilde(path: impl AsRef<Path>) -> Option<PathBuf> {
+    let path = path.as_ref();
+    let mut components = path.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first == "~" => Some(home_dir()?.join(components)),
+        _ => Some(path.to_path_buf()),
+    }
+}
+
+/// Serialize the value as TOML and write it to the file.
+pub fn write_toml(path: &Path, value: &impl Serialize) -> Result<()> {
+    let contents = toml::to_string_pretty(value).map_err(|err| {
+        LdfmError::io(
+            format!("Cannot serialize {}", path.display()),
+            std::io::Error::other(err),
+        )
+    })?;
+    std::fs::write(path, contents).with_path("write", path)
+}
+
+/// Copy a file or a directory tree from `from` to `to`, overwriting existing files.
+///
+/// Files and directories for which `excluded` returns true are skipped.
+/// The closure receives paths relative to `from`.
+/// Files are copied on up to `jobs` threads and `copied` is called with the size of
+/// every copied file. Returns the total size of copied files.
+pub fn copy_tree(
+    from: &Path,
+    to: &Path,
+    excluded: &dyn Fn(&Path) -> bool,
+    copied: &(dyn Fn(u64) + Sync),
+    jobs: usize,
+) -> Result<u64> {
+    let mut files = Vec::new();
+    collect_tree(from, to, Path::new(""), excluded, &mut files)?;
+    copy_files(&files, copied, jobs)
+}
+
+/// Copy files given as pairs of sources and targets on up to `jobs` threads.
+///
+/// Parent directories of targets must exist. `copied` is called with the size of
+/// every copied file. Returns the total size of copied files, or the error
+/// of the first pair that failed.
+pub fn copy_files(
+    files: &[(PathBuf, PathBuf)],
+    copied: &(dyn Fn(u64) + Sync),
+    jobs: usize,
+) -> Result<u64> {
+    parallel_map(files, jobs, |(from, to)| {
+        tracing::debug!("Copying {} -> {}", from.display(), to.display());
+        let bytes = std::fs::copy(from, to).with_path("copy", from)?;
+        copied(bytes);
+        Ok(bytes)
+    })
+    .into_iter()
+    .sum()
+}
+
+/// Create directories of the tree and collect pairs of files to copy.
+fn collect_tree(
+    from: &Path,
+    to: &Path,
+    relative: &Path,
+    excluded: &dyn Fn(&Path) -> bool,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<()> {
+    if excluded(relative) {
+        tracing::debug!("Skipping excluded {}", from.display());
+        return Ok(());
+    }
+    if from.is_dir() {
+        std::fs::create_dir_all(to).with_path("create", to)?;
+        for entry in std::fs::read_dir(from).with_path("read", from)? {
+            let name = entry.with_path("read", from)?.file_name();
+            collect_tree(
+                &from.join(&name),
+                &to.join(&name),
+                &relative.join(&name),
+                excluded,
+                files,
+            )?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent).with_path("create", parent)?;
+        }
+        files.push((from.to_path_buf(), to.to_path_buf()));
     }
     Ok(())
 }
 
-pub fn git_push(repo: &str) -> anyhow::Result<()> {
-    let push_code = run_command("git", ["-C", repo, "push"])?;
-    if !push_code.success() {
-        anyhow::bail!(
-            "Failed to push changes to remote repository: {:?}",
-            push_code
-        );
+/// Call `f` with every item on up to `jobs` threads.
+///
+/// Results are returned in the order of the items, no matter which finished first.
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let jobs = jobs.min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Relative paths of files in the directory, sorted.
+///
+/// Without `recursive`, lists direct children including directories.
+/// `.git` directories are skipped.
+pub fn walk_dir(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir).with_path("read", dir)? {
+        names.push(entry.with_path("read", dir)?.file_name());
+    }
+    names.sort();
+    for name in names {
+        if name == ".git" {
+            continue;
+        }
+        let path = dir.join(&name);
+        if recursive && path.is_dir() {
+            for child in walk_dir(&path, true)? {
+                result.push(PathBuf::from(&name).join(child));
+            }
+        } else {
+            result.push(PathBuf::from(name));
+        }
+    }
+    Ok(result)
+}
+
+/// Remove a file or a directory tree if it exists.
+pub fn remove_path(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path).with_path("remove", path)
+    } else if path.exists() || path.is_symlink() {
+        std::fs::remove_file(path).with_path("remove", path)
+    } else {
+        Ok(())
+    }
+}
+
+/// Result of a finished git command.
+pub struct GitOutput {
+    pub status: ExitStatus,
+    pub stderr: String,
+}
+
+impl GitOutput {
+    fn into_error(self, args: &[&OsStr]) -> LdfmError {
+        LdfmError::Git {
+            args: args
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            status: self.status,
+            stderr: self.stderr,
+        }
+    }
+}
+
+/// Run git with the given arguments.
+///
+/// The output is captured and logged, so git never writes to stdout
+/// of the process embedding ldfm. Stderr is kept to be reported
+/// in case of failure.
+pub fn run_git<T: AsRef<OsStr>>(args: impl IntoIterator<Item = T>) -> Result<GitOutput> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|err| LdfmError::io("Cannot run git", err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    for line in stdout.lines().chain(stderr.lines()) {
+        tracing::debug!("git: {}", line);
+    }
+    Ok(GitOutput {
+        status: output.status,
+        stderr,
+    })
+}
+
+/// Run git and return its stdout, failing if it exits with a non-zero code.
+///
+/// Unlike [`run_git`], stdout isn't logged, since it's usually file contents.
+pub fn git_stdout<T: AsRef<OsStr>>(args: impl IntoIterator<Item = T>) -> Result<Vec<u8>> {
+    let args = args.into_iter().collect::<Vec<_>>();
+    let args = args.iter().map(AsRef::as_ref).collect::<Vec<&OsStr>>();
+    let output = std::process::Command::new("git")
+        .args(&args)
+        .output()
+        .map_err(|err| LdfmError::io("Cannot run git", err))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    for line in stderr.lines() {
+        tracing::debug!("git: {}", line);
+    }
+    if !output.status.success() {
+        return Err(GitOutput {
+            status: output.status,
+            stderr,
+        }
+        .into_error(&args));
+    }
+    Ok(output.stdout)
+}
+
+/// Run git and fail if it exits with a non-zero code.
+pub fn git<T: AsRef<OsStr>>(args: impl IntoIterator<Item = T>) -> Result<()> {
+    let args = args.into_iter().collect::<Vec<_>>();
+    let args = args.iter().map(AsRef::as_ref).collect::<Vec<&OsStr>>();
+    let output = run_git(&args)?;
+    if !output.status.success() {
+        return Err(output.into_error(&args));
     }
     Ok(())
 }
 
-pub fn git_pull(repo: &str) -> anyhow::Result<()> {
-    let push_code = run_command("git", ["-C", repo, "pull"])?;
-    if !push_code.success() {
-        tracing::warn!("Failed to pull changes from the remote repository");
+pub fn git_clone(repo_url: &str, local_path: &str) -> Result<()> {
+    git(["clone", repo_url, local_path])
+}
+
+pub fn git_init(repo: &str, branch: &str) -> Result<()> {
+    git(["init", "--initial-branch", branch, repo])
+}
+
+/// Add a remote to the repository and make it the upstream of `branch`,
+/// so plain `git push` and `git pull` work right after initialization.
+///
+/// An existing remote with the same name is pointed to `url` instead.
+pub fn git_remote_add(repo: &str, name: &str, url: &str, branch: &str) -> Result<()> {
+    let exists = run_git(["-C", repo, "remote", "get-url", name])?
+        .status
+        .success();
+    let action = if exists { "set-url" } else { "add" };
+    git(["-C", repo, "remote", action, name, url])?;
+    let branch_remote = format!("branch.{branch}.remote");
+    let branch_merge = format!("branch.{branch}.merge");
+    let merge_ref = format!("refs/heads/{branch}");
+    git(["-C", repo, "config", &branch_remote, name])?;
+    git(["-C", repo, "config", &branch_merge, &merge_ref])?;
+    Ok(())
+}
+
+pub fn git_push(repo: &str) -> Result<()> {
+    git(["-C", repo, "push"])
+}
+
+pub fn git_pull(repo: &str) -> Result<()> {
+    let output = run_git(["-C", repo, "pull"])?;
+    if !output.status.success() {
+        tracing::warn!(
+            "Failed to pull changes from the remote repository: {}",
+            output.stderr.trim()
+        );
     }
     Ok(())
 }
 
-pub fn git_lfs_pull(repo: &str) -> anyhow::Result<()> {
+pub fn git_lfs_pull(repo: &str) -> Result<()> {
     // Ensure git-lfs is installed and initialized
     let attrs = PathBuf::from(repo).join(".gitattributes");
     // Git LFS uses a .gitattributes file to track large files
@@ -58,30 +341,85 @@ pub fn git_lfs_pull(repo: &str) -> anyhow::Result<()> {
         return Ok(());
     }
     let mut has_lfs = false;
-    for line in BufReader::new(File::open(&attrs)?).lines().flatten() {
+    let attrs_file = File::open(&attrs).with_path("read", &attrs)?;
+    for line in BufReader::new(attrs_file)
+        .lines()
+        .map_while(std::result::Result::ok)
+    {
         if line.contains("filter=lfs") {
             has_lfs = true;
             break;
         }
     }
-    tracing::info!("Found LFS tracked files.");
     if !has_lfs {
         tracing::info!("No LFS tracked files found in the repository.");
         return Ok(());
     }
+    tracing::info!("Found LFS tracked files.");
     tracing::info!("Pulling LFS files for repository: {}", repo);
-    let pull_code = run_command("git", ["-C", repo, "lfs", "pull"])?;
-    if !pull_code.success() {
-        tracing::warn!("Failed to pull LFS files");
+    let output = run_git(["-C", repo, "lfs", "pull"])?;
+    if !output.status.success() {
+        tracing::warn!("Failed to pull LFS files: {}", output.stderr.trim());
     }
     Ok(())
 }
 
-pub fn git_commit(repo: &str, message: &str) -> anyhow::Result<()> {
-    let add_code = run_command("git", ["-C", repo, "add", "."])?;
-    if !add_code.success() {
-        anyhow::bail!("Failed to add changes to git index: {:?}", add_code);
+/// Commit all changes in the repository.
+///
+/// Does nothing if there are no changes to commit.
+/// Returns whether a commit was created.
+pub fn git_commit(repo: &str, message: &str) -> Result<bool> {
+    git(["-C", repo, "add", "."])?;
+    let diff = run_git(["-C", repo, "diff", "--cached", "--quiet"])?;
+    if diff.status.success() {
+        tracing::info!("No changes to commit in {}", repo);
+        return Ok(false);
     }
-    run_command("git", ["-C", repo, "commit", "-m", message])?;
-    Ok(())
+    git(["-C", repo, "commit", "-m", message])?;
+    Ok(true)
+}
+
+/// Commit changes of the given paths only, leaving other changes in the repository alone.
+///
+/// Paths are relative to the repository and may no longer exist.
+/// Does nothing if none of them changed.
+/// Returns whether a commit was created.
+pub fn git_commit_paths(repo: &str, message: &str, paths: &[PathBuf]) -> Result<bool> {
+    let pathspecs = paths
+        .iter()
+        .map(|path| format!(":(literal){}", path.display()))
+        .collect::<Vec<_>>();
+    for (path, pathspec) in paths.iter().zip(&pathspecs) {
+        git([
+            "-C",
+            repo,
+            "rm",
+            "-r",
+            "-q",
+            "--cached",
+            "--ignore-unmatch",
+            "--",
+            pathspec,
+        ])?;
+        if Path::new(repo).join(path).exists() {
+            git(["-C", repo, "add", "--", pathspec])?;
+        }
+    }
+    let mut diff = vec!["-C", repo, "diff", "--cached", "--name-only", "-z", "--"];
+    diff.extend(pathspecs.iter().map(String::as_str));
+    let staged = git_stdout(diff)?;
+    let staged = String::from_utf8_lossy(&staged);
+    let staged = staged
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| format!(":(literal){}", name))
+        .collect::<Vec<_>>();
+    if staged.is_empty() {
+        tracing::info!("No changes to commit in {}", repo);
+        return Ok(false);
+    }
+    let mut commit = vec!["-C", repo, "commit", "-m", message, "--only", "--"];
+    commit.extend(staged.iter().map(String::as_str));
+    git(commit)?;
+    Ok(true)
 }
@@ -0,0 +1,161 @@
+//! Hermetic environment for running the `ldfm` binary end to end.
+//!
+//! Every environment gets its own temporary `HOME`, ldfm config file
+//! and a local bare git repository acting as the remote.
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+pub struct TestEnv {
+    root: tempfile::TempDir,
+    /// Bare repository used as the remote of dotfiles repositories.
+    pub remote: PathBuf,
+}
+
+/// A single machine with its own home directory and ldfm config.
+pub struct Machine {
+    /// Host name of the machine, also the name of its home directory.
+    pub name: String,
+    pub home: PathBuf,
+    pub config: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        let remote = root.path().join("remote.git");
+        git(
+            root.path(),
+            ["init", "--bare", "--initial-branch", "main", "remote.git"],
+        );
+        Self { root, remote }
+    }
+
+    pub fn remote_url(&self) -> String {
+        self.remote.display().to_string()
+    }
+
+    /// Create a new machine with an empty home directory.
+    pub fn machine(&self, name: &str) -> Machine {
+        let home = self.root.path().join(name);
+        std::fs::create_dir_all(&home).unwrap();
+        Machine {
+            name: name.to_string(),
+            config: home.join(".config/ldfm/config.toml"),
+            home,
+        }
+    }
+
+    /// Paths of all files in the remote's `main` branch.
+    pub fn remote_files(&self) -> Vec<String> {
+        let output = git(&self.remote, ["ls-tree", "-r", "--name-only", "main"]);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Contents of the file in the remote's `main` branch.
+    pub fn remote_file(&self, path: &str) -> String {
+        let output = git(&self.remote, ["show", &format!("main:{path}")]);
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+impl Machine {
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.home.join(relative)
+    }
+
+    pub fn repo(&self) -> PathBuf {
+        self.path(".config/dotfiles")
+    }
+
+    pub fn write(&self, relative: &str, contents: &str) {
+        let path = self.path(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, relative: &str) -> String {
+        std::fs::read_to_string(self.path(relative)).unwrap()
+    }
+
+    /// Command running the ldfm binary inside this machine.
+    pub fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_ldfm"));
+        cmd.args(args)
+            .env_remove("LDFM_REPO")
+            .env("HOME", &self.home)
+            .env("LDFM_HOME", &self.home)
+            .env("LDFM_CONFIG", &self.config)
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .env("XDG_STATE_HOME", self.home.join(".local/state"))
+            .env("LDFM_HOST", &self.name)
+            .env_remove("LDFM_STATE_DIR");
+        isolate_git(&mut cmd);
+        cmd
+    }
+
+    /// Run ldfm and assert that it succeeded.
+    pub fn ldfm<I, S>(&self, args: I) -> Output
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let output = self.command(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "ldfm failed with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Subjects of all commits in the dotfiles repository, newest first.
+    pub fn commits(&self) -> Vec<String> {
+        let output = git(&self.repo(), ["log", "--format=%s"]);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Make git independent of the user's configuration.
+fn isolate_git(cmd: &mut Command) {
+    cmd.env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_AUTHOR_NAME", "ldfm")
+        .env("GIT_AUTHOR_EMAIL", "ldfm@example.com")
+        .env("GIT_COMMITTER_NAME", "ldfm")
+        .env("GIT_COMMITTER_EMAIL", "ldfm@example.com");
+}
+
+pub fn git<I, S>(dir: &Path, args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir).args(args);
+    isolate_git(&mut cmd);
+    let output = cmd.output().unwrap();
+    assert!(
+        output.status.success(),
+        "git failed with {}:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{TestEnv, git};
+
+#[test]
+fn not_initialized() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    let output = laptop.command(["list"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Hint: Run `ldfm init` first"), "{stderr}");
+}
+
+#[test]
+fn broken_config() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.write(".config/ldfm/config.toml", "repos = [");
+    let output = laptop.command(["list"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn push_failure() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    std::fs::remove_dir_all(&env.remote).unwrap();
+
+    let output = laptop.command(["commit", "--push"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`git -C"), "{stderr}");
+}
+
+#[test]
+fn reinitialization_conflict() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    let output = laptop
+        .command(["init", "--new", "--local-path", "~/other"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn unknown_repository() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    let output = laptop
+        .command(["list", "--repo", "missing"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn secrets_block_commit() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(
+        ".npmrc",
+        "color=true\n//registry.npmjs.org/:_authToken=secret\n",
+    );
+    laptop.write(".ssh/id_ed25519", "key\n");
+    laptop.write(".ssh/config", "Host *\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.npmrc"]);
+    laptop.ldfm(["track", "~/.ssh"]);
+
+    let output = laptop.command(["commit"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(".npmrc:2: npm token"), "{stderr}");
+    assert!(
+        stderr.contains(".ssh/id_ed25519: credential file"),
+        "{stderr}"
+    );
+    assert_eq!(laptop.commits(), ["Initialized ldfm"]);
+    assert!(!laptop.repo().join(".npmrc").exists());
+
+    let config = laptop.repo().join("ldfm.toml");
+    let mut contents = std::fs::read_to_string(&config).unwrap();
+    contents.push_str("\n[secrets]\nallow = [\".npmrc\", \"id_*\"]\n");
+    std::fs::write(&config, contents).unwrap();
+    laptop.ldfm(["commit"]);
+    assert!(laptop.repo().join(".ssh/id_ed25519").exists());
+}
+
+#[test]
+fn secrets_in_one_repo_dont_stop_others() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "company",
+        "--local-path",
+        "~/company",
+    ]);
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "personal",
+        "--priority",
+        "10",
+        "--local-path",
+        "~/personal",
+    ]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".npmrc", "//registry.npmjs.org/:_authToken=secret\n");
+    laptop.ldfm(["--repo", "company", "track", "~/.bashrc"]);
+    laptop.ldfm(["--repo", "personal", "track", "~/.npmrc"]);
+
+    let output = laptop.command(["commit"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(9));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("company: .bashrc"), "{stdout}");
+    let log = git(&laptop.path("company"), ["log", "--format=%s"]);
+    assert!(
+        String::from_utf8_lossy(&log.stdout).starts_with("Dotfiles sync."),
+        "company repository is committed"
+    );
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::{os::unix::fs::PermissionsExt, process::Command};
+
+use common::TestEnv;
+
+fn prepare(env: &TestEnv) -> common::Machine {
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\nLDFM_EOF\n");
+    laptop.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    laptop.write(".local/bin/tool", "#!/bin/sh\necho '100%'");
+    let tool = laptop.path(".local/bin/tool");
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    laptop.ldfm(["track", "~/.local/bin/tool"]);
+    laptop.ldfm(["commit"]);
+    laptop
+}
+
+fn assert_exported(home: &std::path::Path) {
+    let read = |path: &str| std::fs::read_to_string(home.join(path)).unwrap();
+    assert_eq!(read(".bashrc"), "alias ll='ls -l'\nLDFM_EOF\n");
+    assert_eq!(read(".config/nvim/init.lua"), "vim.o.number = true\n");
+    assert_eq!(read(".local/bin/tool"), "#!/bin/sh\necho '100%'");
+    let mode = std::fs::metadata(home.join(".local/bin/tool"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+}
+
+#[test]
+fn export_script() {
+    let env = TestEnv::new();
+    let laptop = prepare(&env);
+    laptop.ldfm(["export", "--output", "~/dotfiles.sh"]);
+
+    let server = env.machine("server");
+    let status = Command::new("sh")
+        .arg(laptop.path("dotfiles.sh"))
+        .arg(&server.home)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_exported(&server.home);
+}
+
+#[test]
+fn export_tarball() {
+    let env = TestEnv::new();
+    let laptop = prepare(&env);
+    laptop.ldfm(["export", "-o", "~/dotfiles.tgz"]);
+
+    let server = env.machine("server");
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(laptop.path("dotfiles.tgz"))
+        .arg("-C")
+        .arg(&server.home)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_exported(&server.home);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{TestEnv, git};
+
+#[test]
+fn import_stow() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write("stow/bash/.bashrc", "alias ll='ls -l'\n");
+    laptop.write("stow/nvim/.config/nvim/init.lua", "vim.o.number = true\n");
+    laptop.write("stow/nvim/.config/nvim/lua/plugins.lua", "return {}\n");
+    laptop.write("stow/git/dot-gitconfig", "[user]\n");
+    // Links `stow --dotfiles bash nvim git` creates in the home directory.
+    std::fs::create_dir_all(laptop.path(".config")).unwrap();
+    for (link, file) in [
+        (".bashrc", "stow/bash/.bashrc"),
+        (".config/nvim", "stow/nvim/.config/nvim"),
+        (".gitconfig", "stow/git/dot-gitconfig"),
+    ] {
+        std::os::unix::fs::symlink(laptop.path(file), laptop.path(link)).unwrap();
+    }
+
+    let output = laptop.ldfm(["import", "--from", "stow", "~/stow"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Imported 4 files"), "{stderr}");
+    assert_eq!(laptop.commits()[0], "Imported dotfiles from stow.");
+    // Entries point to the home directory, not into the stow packages.
+    let toml = std::fs::read_to_string(laptop.repo().join("ldfm.toml")).unwrap();
+    assert!(toml.contains("\".bashrc\" = \"~/.bashrc\""), "{toml}");
+    assert!(toml.contains("nvim = \"~/.config/nvim\""), "{toml}");
+    assert!(!toml.contains("stow"), "{toml}");
+
+    // After `stow -D` removes the links, apply puts the files back into the home directory.
+    for link in [".bashrc", ".config/nvim", ".gitconfig"] {
+        std::fs::remove_file(laptop.path(link)).unwrap();
+    }
+    std::fs::remove_dir_all(laptop.path("stow")).unwrap();
+    laptop.ldfm(["apply", "--no-pull"]);
+    assert!(!laptop.path(".bashrc").is_symlink());
+    assert_eq!(laptop.read(".bashrc"), "alias ll='ls -l'\n");
+    assert_eq!(laptop.read(".gitconfig"), "[user]\n");
+    assert_eq!(laptop.read(".config/nvim/lua/plugins.lua"), "return {}\n");
+}
+
+#[test]
+fn import_chezmoi() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write("chezmoi/dot_bashrc", "alias ll='ls -l'\n");
+    laptop.write("chezmoi/private_dot_config/git/config", "[user]\n");
+    laptop.write("chezmoi/dot_gitconfig.tmpl", "{{ .email }}\n");
+    laptop.write("chezmoi/run_once_install.sh", "echo hi\n");
+    laptop.write("chezmoi/.chezmoiignore", "README.md\n");
+
+    let output = laptop.ldfm(["import", "--from", "chezmoi", "~/chezmoi"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for untranslated in ["templates", "scripts", ".chezmoiignore", "private_"] {
+        assert!(stderr.contains(untranslated), "{untranslated}: {stderr}");
+    }
+
+    laptop.ldfm(["apply", "--no-pull"]);
+    assert_eq!(laptop.read(".bashrc"), "alias ll='ls -l'\n");
+    assert_eq!(laptop.read(".config/git/config"), "[user]\n");
+    assert!(!laptop.path(".gitconfig").exists());
+}
+
+#[test]
+fn import_yadm() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    let work_tree = laptop.path("yadm-work");
+    laptop.write("yadm-work/.bashrc##default", "alias ll='ls -l'\n");
+    laptop.write("yadm-work/.bashrc##os.Darwin", "alias ll='ls -lG'\n");
+    laptop.write("yadm-work/.vimrc", "set number\n");
+    laptop.write("yadm-work/.config/yadm/bootstrap", "#!/bin/sh\n");
+    git(&work_tree, ["init", "--initial-branch", "main"]);
+    git(&work_tree, ["add", "."]);
+    git(&work_tree, ["commit", "-m", "Dotfiles"]);
+    git(&laptop.home, ["clone", "--bare", "yadm-work", "repo.git"]);
+
+    let output = laptop.ldfm(["import", "--from", "yadm", "~/repo.git"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(".bashrc##os.Darwin"), "{stderr}");
+    assert!(stderr.contains("bootstrap"), "{stderr}");
+
+    laptop.ldfm(["apply", "--no-pull"]);
+    assert_eq!(laptop.read(".bashrc"), "alias ll='ls -l'\n");
+    assert_eq!(laptop.read(".vimrc"), "set number\n");
+}
@@ -0,0 +1,105 @@
+//! Uses the library API directly instead of running the binary.
+use std::sync::{Arc, Mutex};
+
+use ldfm::{InitOptions, Ldfm, progress::Progress};
+
+/// Progress that records its events.
+#[derive(Debug, Clone, Default)]
+struct Events(Arc<Mutex<Vec<String>>>);
+
+impl Events {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Progress for Events {
+    fn start(&self, entries: usize) {
+        self.0.lock().unwrap().push(format!("start {entries}"));
+    }
+
+    fn entry(&self, _repo: &str, key: &str) {
+        self.0.lock().unwrap().push(format!("entry {key}"));
+    }
+
+    fn entry_done(&self) {
+        self.0.lock().unwrap().push("done".to_string());
+    }
+
+    fn finish(&self) {
+        self.0.lock().unwrap().push("finish".to_string());
+    }
+}
+
+#[test]
+fn track_commit_and_plan_apply() {
+    let root = tempfile::tempdir().unwrap();
+    let home = root.path().join("home");
+    std::fs::create_dir_all(&home).unwrap();
+    // SAFETY: this is the only test in the binary, nothing else reads the environment.
+    unsafe {
+        std::env::set_var("LDFM_HOME", &home);
+        std::env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+        std::env::set_var("GIT_CONFIG_GLOBAL", "/dev/null");
+        for var in ["GIT_AUTHOR_NAME", "GIT_COMMITTER_NAME"] {
+            std::env::set_var(var, "ldfm");
+        }
+        for var in ["GIT_AUTHOR_EMAIL", "GIT_COMMITTER_EMAIL"] {
+            std::env::set_var(var, "ldfm@example.com");
+        }
+    }
+
+    let ldfm = Ldfm::init(
+        &root.path().join("config.toml"),
+        None,
+        InitOptions {
+            name: "personal".to_string(),
+            priority: 0,
+            local_path: root.path().join("dotfiles"),
+            git_repo: None,
+            new: true,
+            branch: "main".to_string(),
+            force: false,
+        },
+    )
+    .unwrap();
+    let events = Events::default();
+    let ldfm = ldfm.with_progress(events.clone());
+
+    std::fs::write(home.join(".bashrc"), "alias ll='ls -l'\n").unwrap();
+    let outcome = ldfm.track(&home.join(".bashrc")).unwrap();
+    assert_eq!(outcome.repo, "personal");
+    assert_eq!(outcome.key, ".bashrc");
+    assert!(!outcome.already_tracked);
+    assert!(ldfm.track(&home.join(".bashrc")).unwrap().already_tracked);
+
+    let reports = ldfm.commit(false).unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].copied, [".bashrc"]);
+    assert!(reports[0].committed);
+    // Entries are counted as done only once they finish.
+    assert_eq!(
+        events.take(),
+        ["start 1", "entry .bashrc", "done", "finish"]
+    );
+    assert!(!ldfm.commit(false).unwrap()[0].committed);
+
+    std::fs::write(home.join(".bashrc"), "alias la='ls -a'\n").unwrap();
+    let plan = ldfm.plan_apply().unwrap();
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!(plan.actions[0].target, home.join(".bashrc"));
+    events.take();
+    ldfm.apply_plan(&plan).unwrap();
+    assert_eq!(
+        events.take(),
+        ["start 1", "entry .bashrc", "done", "finish"]
+    );
+    assert_eq!(
+        std::fs::read_to_string(home.join(".bashrc")).unwrap(),
+        "alias ll='ls -l'\n"
+    );
+
+    let untracked = ldfm.untrack(&home.join(".bashrc")).unwrap().unwrap();
+    assert!(untracked.removed_copy.is_some());
+    assert!(ldfm.list().unwrap().is_empty());
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use std::{io::Write, process::Stdio};
+
+use common::TestEnv;
+
+fn json(output: std::process::Output) -> serde_json::Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn list_json() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".config/fish/config.fish", "set -x EDITOR nvim\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/fish"]);
+
+    let entries = json(laptop.ldfm(["list", "--format", "json"]));
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["key"], ".bashrc");
+    assert_eq!(entries[0]["target"], "~/.bashrc");
+    assert_eq!(entries[0]["entry_type"], "file");
+    assert_eq!(entries[0]["live_exists"], true);
+    assert_eq!(entries[0]["repo_exists"], false);
+    assert_eq!(entries[1]["key"], "fish");
+    assert_eq!(entries[1]["entry_type"], "directory");
+    assert!(entries[0].get("state").is_none());
+}
+
+#[test]
+fn status_json() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".profile", "export EDITOR=nvim\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["commit"]);
+    laptop.ldfm(["track", "~/.profile"]);
+    laptop.write(".vimrc", "set nonumber\n");
+
+    let entries = json(laptop.ldfm(["status", "--format", "json"]));
+    let states = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry["key"].as_str().unwrap(),
+                entry["state"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        states,
+        [
+            (".bashrc", "synced"),
+            (".profile", "missing_repo"),
+            (".vimrc", "modified")
+        ]
+    );
+}
+
+#[test]
+fn unmanaged_files() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".bash_history", "ls\n");
+    laptop.write("notes.txt", "not a dotfile\n");
+    laptop.write(".config/fish/config.fish", "set -x EDITOR nvim\n");
+    laptop.write(".config/alacritty/alacritty.toml", "[font]\n");
+    laptop.write(".config/git/config", "[user]\n");
+    laptop.write(".config/git/ignore", "*.swp\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/fish"]);
+    laptop.ldfm(["track", "~/.config/git/config"]);
+
+    let entries = json(laptop.ldfm(["unmanaged", "--format", "json"]));
+    let paths = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["path"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        ["~/.config/alacritty", "~/.config/git/ignore", "~/.vimrc"]
+    );
+
+    let output = laptop
+        .ldfm(["unmanaged", "--ignore", "alacritty", "--format", "json"])
+        .stdout;
+    assert!(!String::from_utf8_lossy(&output).contains("alacritty"));
+
+    let mut child = laptop
+        .command(["unmanaged", "--interactive"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"y\nn\ny\n").unwrap();
+    assert!(child.wait().unwrap().success());
+    let entries = json(laptop.ldfm(["list", "--format", "json"]));
+    let targets = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["target"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(targets.contains(&"~/.config/alacritty"));
+    assert!(!targets.contains(&"~/.config/git/ignore"));
+    assert!(targets.contains(&"~/.vimrc"));
+}
+
+#[test]
+fn complete_tracked_paths() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".bash_profile", "source ~/.bashrc\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+
+    let complete = |words: &[&str]| {
+        let output = laptop
+            .command(["--", "ldfm"].iter().chain(words))
+            .env("COMPLETE", "fish")
+            .current_dir(laptop.path(""))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        complete(&["untrack", "~/"]),
+        "~/.bashrc\tdefault: .bashrc\n"
+    );
+    assert_eq!(complete(&["rename-key", ".b"]), ".bashrc\t~/.bashrc\n");
+    let candidates = complete(&["track", ".bash"]);
+    let mut candidates = candidates.lines().collect::<Vec<_>>();
+    candidates.sort();
+    assert_eq!(candidates, [".bash_profile", ".bashrc\ttracked"]);
+}
+
+#[test]
+fn status_reports_broken_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new"]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".config/gh/config.yml", "aliases:\n  co: pr checkout\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/gh/config.yml", "--merge", "aliases"]);
+    laptop.ldfm(["commit"]);
+    laptop.write(".config/gh/config.yml", "aliases: [\n");
+
+    let entries = json(laptop.ldfm(["list", "--format", "json"]));
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].get("error").is_none());
+    assert!(entries[1]["error"].as_str().is_some());
+
+    let entries = json(laptop.ldfm(["status", "--format", "json"]));
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries[0]["key"], ".bashrc");
+    assert_eq!(entries[0]["state"], "synced");
+    assert_eq!(entries[1]["key"], "config.yml");
+    assert_eq!(entries[1]["state"], "error");
+    assert!(entries[1]["error"].as_str().is_some());
+
+    let output = laptop.ldfm(["status"]).stdout;
+    assert!(String::from_utf8_lossy(&output).contains("error        ~/.config/gh/config.yml: "));
+}
@@ -0,0 +1,901 @@
+mod common;
+
+use common::{TestEnv, git};
+
+#[test]
+fn init_new_creates_repository() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+
+    let repo = laptop.repo();
+    for name in [".git", "ldfm.toml", ".gitignore", ".gitattributes"] {
+        assert!(repo.join(name).exists(), "{name} is missing");
+    }
+    assert_eq!(laptop.commits(), ["Initialized ldfm"]);
+    assert!(laptop.config.exists());
+
+    let output = laptop.command(["init", "--new"]).output().unwrap();
+    assert!(!output.status.success());
+
+    // Reinitializing keeps working when the repository already has a remote.
+    let other = env.machine("other").path("remote.git");
+    git(
+        &env.remote,
+        ["clone", "--bare", ".", &other.display().to_string()],
+    );
+    for remote in [env.remote_url(), other.display().to_string()] {
+        laptop.ldfm(["init", "--new", "--force", &remote]);
+        let url = git(&repo, ["remote", "get-url", "origin"]);
+        assert_eq!(String::from_utf8_lossy(&url.stdout).trim(), remote);
+    }
+}
+
+#[test]
+fn commit_push_and_apply() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    laptop.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    let remote_files = env.remote_files();
+    for path in [
+        ".bashrc",
+        "nvim/init.lua",
+        "nvim/lua/plugins.lua",
+        "ldfm.toml",
+        ".gitignore",
+        ".gitattributes",
+    ] {
+        assert!(
+            remote_files.contains(&path.to_string()),
+            "{path} was not pushed"
+        );
+    }
+    assert_eq!(env.remote_file(".bashrc"), "alias ll='ls -l'\n");
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -l'\n");
+    assert_eq!(
+        server.read(".config/nvim/init.lua"),
+        "vim.o.number = true\n"
+    );
+    assert_eq!(server.read(".config/nvim/lua/plugins.lua"), "return {}\n");
+
+    // Changes made on the laptop reach the server on the next apply.
+    laptop.write(".bashrc", "alias la='ls -a'\n");
+    laptop.ldfm(["commit", "-p"]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".bashrc"), "alias la='ls -a'\n");
+}
+
+#[test]
+fn commit_removes_stale_files() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".config/git/config", "[user]\n");
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["track", "~/.config/git"]);
+    laptop.ldfm(["commit", "-p"]);
+    assert!(laptop.repo().join(".vimrc").exists());
+
+    laptop.ldfm(["untrack", "~/.vimrc"]);
+    std::fs::remove_file(laptop.path(".config/git/config")).unwrap();
+    laptop.write(".config/git/ignore", "*.swp\n");
+    laptop.ldfm(["commit", "-p"]);
+
+    let remote_files = env.remote_files();
+    assert!(!remote_files.contains(&".vimrc".to_string()));
+    assert!(!remote_files.contains(&"git/config".to_string()));
+    assert!(remote_files.contains(&"git/ignore".to_string()));
+    assert!(remote_files.contains(&".gitignore".to_string()));
+    // The live files are never touched by commit.
+    assert_eq!(laptop.read(".vimrc"), "set number\n");
+}
+
+#[test]
+fn commit_keeps_entries_missing_on_this_machine() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".zshrc", "setopt autocd\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.zshrc"]);
+    laptop.ldfm(["commit", "-p"]);
+
+    std::fs::remove_file(laptop.path(".zshrc")).unwrap();
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    let output = laptop.ldfm(["commit", "-p"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "updated    default: .bashrc\n\
+         skipped    default: .zshrc (not found on this machine)\n"
+    );
+    assert_eq!(env.remote_file(".zshrc"), "setopt autocd\n");
+    assert_eq!(env.remote_file(".bashrc"), "alias ll='ls -la'\n");
+}
+
+#[test]
+fn apply_overwrites_local_changes() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".profile", "export EDITOR=nvim\n");
+    laptop.ldfm(["track", "~/.profile"]);
+    laptop.ldfm(["commit", "-p"]);
+
+    laptop.write(".profile", "export EDITOR=nano\n");
+    laptop.ldfm(["apply", "--no-pull"]);
+    assert_eq!(laptop.read(".profile"), "export EDITOR=nvim\n");
+}
+
+#[test]
+fn commit_skips_excluded_files() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    laptop.write(".config/nvim/lazy-lock.json", "{}\n");
+    laptop.write(".config/nvim/cache/state", "state\n");
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    let repo_config = laptop.repo().join("ldfm.toml");
+    let contents = std::fs::read_to_string(&repo_config).unwrap();
+    std::fs::write(
+        &repo_config,
+        format!("exclude = [\"lazy-lock.json\", \"/cache\"]\n{contents}"),
+    )
+    .unwrap();
+    laptop.ldfm(["commit", "-p"]);
+
+    let remote_files = env.remote_files();
+    assert!(remote_files.contains(&"nvim/init.lua".to_string()));
+    assert!(!remote_files.contains(&"nvim/lazy-lock.json".to_string()));
+    assert!(!remote_files.contains(&"nvim/cache/state".to_string()));
+}
+
+#[test]
+fn same_file_names() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".config/git/config", "[user]\n");
+    laptop.write(".ssh/config", "Host *\n");
+    laptop.ldfm(["track", "~/.config/git/config"]);
+    laptop.ldfm(["track", "~/.ssh/config"]);
+    laptop.ldfm(["commit", "-p"]);
+    assert_eq!(env.remote_file("config"), "[user]\n");
+    assert_eq!(env.remote_file("0-config"), "Host *\n");
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".config/git/config"), "[user]\n");
+    assert_eq!(server.read(".ssh/config"), "Host *\n");
+}
+
+#[test]
+fn block_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(
+        ".bashrc",
+        "export LAPTOP=1\n# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\n",
+    );
+    laptop.ldfm(["track", "~/.bashrc", "--block", "aliases"]);
+    laptop.ldfm(["commit", "-p"]);
+    assert_eq!(env.remote_file(".bashrc.aliases"), "alias ll='ls -l'\n");
+
+    // Only the block is replaced, the rest of the file stays machine-specific.
+    let server = env.machine("server");
+    server.write(".bashrc", "export SERVER=1\n");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(
+        server.read(".bashrc"),
+        "export SERVER=1\n# >>> ldfm:aliases >>>\nalias ll='ls -l'\n# <<< ldfm:aliases <<<\n"
+    );
+
+    server.write(
+        ".bashrc",
+        "# >>> ldfm:aliases >>>\nalias la='ls -a'\n# <<< ldfm:aliases <<<\nexport SERVER=2\n",
+    );
+    server.ldfm(["commit", "-p"]);
+    laptop.ldfm(["apply"]);
+    assert_eq!(
+        laptop.read(".bashrc"),
+        "export LAPTOP=1\n# >>> ldfm:aliases >>>\nalias la='ls -a'\n# <<< ldfm:aliases <<<\n"
+    );
+}
+
+#[test]
+fn merge_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(
+        ".config/Code/User/settings.json",
+        r#"{"editor.fontSize": 14, "window.zoomLevel": 1}"#,
+    );
+    laptop.write(
+        ".config/gh/config.yml",
+        "git_protocol: ssh\naliases:\n  co: pr checkout\n",
+    );
+    laptop.ldfm([
+        "track",
+        "~/.config/Code/User/settings.json",
+        "--merge",
+        "editor.fontSize",
+    ]);
+    laptop.ldfm(["track", "~/.config/gh/config.yml", "--merge", "aliases/co"]);
+    laptop.ldfm(["commit", "-p"]);
+    assert_eq!(
+        env.remote_file("settings.json"),
+        "{\n  \"editor.fontSize\": 14\n}\n"
+    );
+    assert_eq!(
+        env.remote_file("config.yml"),
+        "aliases:\n  co: pr checkout\n"
+    );
+
+    // Keys that aren't managed by ldfm are preserved.
+    let server = env.machine("server");
+    server.write(
+        ".config/Code/User/settings.json",
+        r#"{"editor.fontSize": 10, "telemetry.telemetryLevel": "off"}"#,
+    );
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(
+        server.read(".config/Code/User/settings.json"),
+        "{\n  \"editor.fontSize\": 14,\n  \"telemetry.telemetryLevel\": \"off\"\n}\n"
+    );
+    assert_eq!(
+        server.read(".config/gh/config.yml"),
+        "aliases:\n  co: pr checkout\n"
+    );
+
+    // Merging would drop comments, so files with comments are left alone.
+    let commented = "# Written by gh\naliases:\n  co: pr view\n";
+    server.write(".config/gh/config.yml", commented);
+    let output = server.command(["apply"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would drop its comments"), "{stderr}");
+    assert_eq!(server.read(".config/gh/config.yml"), commented);
+}
+
+#[test]
+fn edit_applies_and_commits() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    std::fs::write(laptop.repo().join("notes.md"), "unrelated\n").unwrap();
+
+    let output = laptop
+        .command(["edit", "~/.bashrc", "--commit"])
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i s/ll/la/")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(laptop.read(".bashrc"), "alias la='ls -l'\n");
+    assert_eq!(
+        std::fs::read_to_string(laptop.repo().join(".bashrc")).unwrap(),
+        "alias la='ls -l'\n"
+    );
+    assert_eq!(laptop.commits()[0], "Dotfiles sync: .bashrc.");
+    // Only the edited entry and its config are committed.
+    let status = git(&laptop.repo(), ["status", "--porcelain"]);
+    assert_eq!(String::from_utf8_lossy(&status.stdout), "?? notes.md\n");
+
+    // Nothing is applied if the editor fails.
+    let output = laptop
+        .command(["edit", "~/.bashrc"])
+        .env("VISUAL", "false")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn pattern_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".config/fish/functions/ll.fish", "function ll; end\n");
+    laptop.write(".config/fish/functions/README.md", "# Functions\n");
+    laptop.ldfm(["track", "~/.config/fish/functions/*.fish"]);
+    laptop.ldfm(["commit", "-p"]);
+    assert!(
+        env.remote_files()
+            .contains(&"functions/ll.fish".to_string())
+    );
+    assert!(
+        !env.remote_files()
+            .contains(&"functions/README.md".to_string())
+    );
+
+    // New matching files are tracked automatically.
+    laptop.write(".config/fish/functions/la.fish", "function la; end\n");
+    laptop.ldfm(["commit", "-p"]);
+    assert_eq!(env.remote_file("functions/la.fish"), "function la; end\n");
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(
+        server.read(".config/fish/functions/ll.fish"),
+        "function ll; end\n"
+    );
+    assert_eq!(
+        server.read(".config/fish/functions/la.fish"),
+        "function la; end\n"
+    );
+    assert!(!server.path(".config/fish/functions/README.md").exists());
+    let status = server.ldfm(["status"]).stdout;
+    assert!(String::from_utf8_lossy(&status).starts_with("synced"));
+}
+
+#[test]
+fn apply_history_and_rollback() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    let server = env.machine("server");
+    server.write(".bashrc", "export EDITOR=vi\n");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -l'\n");
+
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    laptop.ldfm(["commit", "--push"]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -la'\n");
+    assert_eq!(server.read(".vimrc"), "set number\n");
+    assert_eq!(server.read(".config/nvim/lua/plugins.lua"), "return {}\n");
+
+    let output = server.ldfm(["--format", "json", "history"]);
+    let history: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1]["id"], 2);
+    assert_eq!(history[1]["files"].as_array().unwrap().len(), 3);
+
+    server.ldfm(["rollback"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -l'\n");
+    assert!(!server.path(".vimrc").exists());
+    // Directories created by the apply are removed as well.
+    assert!(!server.path(".config/nvim").exists());
+    assert!(server.path(".config/ldfm").exists());
+
+    // Ids of rolled back applies are never given out again.
+    let output = server.ldfm(["apply", "--no-pull"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ldfm rollback 3"), "{stderr}");
+    server.ldfm(["rollback", "1"]);
+    assert_eq!(server.read(".bashrc"), "export EDITOR=vi\n");
+    assert!(!server.path(".vimrc").exists());
+    let output = server.ldfm(["history"]);
+    assert!(output.stdout.is_empty());
+    assert!(
+        !server
+            .command(["rollback"])
+            .output()
+            .unwrap()
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn failed_apply_changes_nothing() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    let server = env.machine("server");
+    server.write(".bashrc", "export EDITOR=vi\n");
+    // A directory in place of a tracked file can't be overwritten.
+    server.write(".vimrc/colors.vim", "colorscheme desert\n");
+    server.ldfm(["init", &env.remote_url()]);
+    let output = server.command(["apply"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(server.read(".bashrc"), "export EDITOR=vi\n");
+    let names = std::fs::read_dir(&server.home)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains("ldfm-"))
+        .collect::<Vec<_>>();
+    assert!(names.is_empty(), "{names:?}");
+    assert!(server.ldfm(["history"]).stdout.is_empty());
+}
+
+#[test]
+fn apply_revision() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["commit"]);
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    laptop.ldfm(["commit", "--push"]);
+    // A colleague's branch with a change that isn't merged yet.
+    let repo = laptop.repo();
+    git(&repo, ["checkout", "-b", "feature"]);
+    std::fs::write(repo.join(".bashrc"), "alias ll='ls -lh'\n").unwrap();
+    git(&repo, ["commit", "-am", "Feature"]);
+    git(&repo, ["push", "origin", "feature"]);
+    git(&repo, ["checkout", "main"]);
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply", "--rev", "HEAD~1"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -l'\n");
+    let status = |args: &[&str]| {
+        let output = server.ldfm([&["status"], args].concat());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert!(status(&["--rev", "HEAD~1"]).starts_with("synced"));
+    assert!(status(&[]).starts_with("modified"));
+
+    server.ldfm(["apply", "--rev", "origin/feature"]);
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -lh'\n");
+    // The checkout is left untouched.
+    assert_eq!(
+        std::fs::read_to_string(server.repo().join(".bashrc")).unwrap(),
+        "alias ll='ls -la'\n"
+    );
+
+    let output = server
+        .command(["apply", "--no-pull", "--rev", "missing"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn apply_revision_of_one_repo() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "company",
+        "--local-path",
+        "~/company",
+    ]);
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "personal",
+        "--priority",
+        "10",
+        "--local-path",
+        "~/personal",
+    ]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.ldfm(["--repo", "personal", "track", "~/.bashrc"]);
+    laptop.ldfm(["--repo", "company", "track", "~/.vimrc"]);
+    laptop.ldfm(["commit"]);
+    git(&laptop.path("personal"), ["tag", "v1"]);
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    laptop.write(".vimrc", "set nonumber\n");
+
+    // The company repository has no such tag, so its files are left alone.
+    let output = laptop.ldfm(["apply", "--no-pull", "--rev", "v1"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Revision v1 is not found in company repository"),
+        "{stderr}"
+    );
+    assert_eq!(laptop.read(".bashrc"), "alias ll='ls -l'\n");
+    assert_eq!(laptop.read(".vimrc"), "set nonumber\n");
+
+    let output = laptop
+        .command(["apply", "--no-pull", "--rev", "missing"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Revision missing is not found in any repository"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn host_overlays() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".gitconfig", "[user]\nemail = me@home\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.gitconfig"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    server.write(".gitconfig", "[user]\nemail = me@work\n");
+    server.ldfm(["commit", "--push", "--host-local", "~/.gitconfig"]);
+    assert_eq!(env.remote_file(".gitconfig"), "[user]\nemail = me@home\n");
+    assert_eq!(
+        env.remote_file("hosts/server/.gitconfig"),
+        "[user]\nemail = me@work\n"
+    );
+
+    // Both machines keep committing without overwriting each other.
+    laptop.ldfm(["apply"]);
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    laptop.ldfm(["commit", "--push"]);
+    assert_eq!(laptop.read(".gitconfig"), "[user]\nemail = me@home\n");
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".gitconfig"), "[user]\nemail = me@work\n");
+    assert_eq!(server.read(".bashrc"), "alias ll='ls -la'\n");
+    server.write(".gitconfig", "[user]\nemail = me@office\n");
+    server.ldfm(["commit", "--push"]);
+    assert_eq!(env.remote_file(".gitconfig"), "[user]\nemail = me@home\n");
+    assert_eq!(
+        env.remote_file("hosts/server/.gitconfig"),
+        "[user]\nemail = me@office\n"
+    );
+
+    std::fs::write(server.repo().join("notes.md"), "unrelated\n").unwrap();
+    server.ldfm(["overlay", "promote", "~/.gitconfig"]);
+    // Only the promoted entry is committed.
+    let status = git(&server.repo(), ["status", "--porcelain"]);
+    assert_eq!(String::from_utf8_lossy(&status.stdout), "?? notes.md\n");
+    assert_eq!(server.commits()[0], "Promoted .gitconfig from server.");
+    let overlays = git(&server.repo(), ["ls-files", "hosts"]);
+    assert!(overlays.stdout.is_empty());
+    std::fs::remove_file(server.repo().join("notes.md")).unwrap();
+    server.ldfm(["commit", "--push"]);
+    assert_eq!(env.remote_file(".gitconfig"), "[user]\nemail = me@office\n");
+    assert!(
+        !env.remote_files()
+            .iter()
+            .any(|file| file.starts_with("hosts/"))
+    );
+    laptop.ldfm(["apply"]);
+    assert_eq!(laptop.read(".gitconfig"), "[user]\nemail = me@office\n");
+}
+
+#[test]
+fn move_and_rename_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    laptop.ldfm(["mv", "~/.vimrc", "~/.config/vim/vimrc"]);
+    assert!(!laptop.path(".vimrc").exists());
+    assert_eq!(laptop.read(".config/vim/vimrc"), "set number\n");
+    let output = laptop
+        .command(["mv", "~/.config/vim/vimrc", "~/.bashrc"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+
+    // Entries inside a moved directory move with it.
+    laptop.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    laptop.ldfm(["track", "~/.config/nvim/lua"]);
+    laptop.ldfm(["mv", "~/.config/nvim", "~/.config/neovim"]);
+    let config = std::fs::read_to_string(laptop.repo().join("ldfm.toml")).unwrap();
+    assert!(
+        config.contains("lua = \"~/.config/neovim/lua\""),
+        "{config}"
+    );
+
+    laptop.ldfm(["rename-key", ".vimrc", "vimrc"]);
+    assert!(laptop.repo().join("vimrc").exists());
+    assert!(!laptop.repo().join(".vimrc").exists());
+    for (from, to) in [("vimrc", ".bashrc"), (".bashrc", "ldfm.toml")] {
+        let output = laptop.command(["rename-key", from, to]).output().unwrap();
+        assert!(!output.status.success(), "{from} -> {to}");
+    }
+    laptop.ldfm(["commit", "--push"]);
+    assert_eq!(env.remote_file("vimrc"), "set number\n");
+    assert!(!env.remote_files().contains(&".vimrc".to_string()));
+
+    let server = env.machine("server");
+    server.ldfm(["init", &env.remote_url()]);
+    server.ldfm(["apply"]);
+    assert_eq!(server.read(".config/vim/vimrc"), "set number\n");
+    assert!(!server.path(".vimrc").exists());
+    assert_eq!(server.read(".config/neovim/lua/plugins.lua"), "return {}\n");
+}
+
+#[test]
+fn forget_and_purge_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["commit"]);
+
+    laptop.ldfm(["forget", "~/.vimrc"]);
+    assert!(laptop.repo().join(".vimrc").exists());
+    assert_eq!(laptop.read(".vimrc"), "set number\n");
+    let config = std::fs::read_to_string(laptop.repo().join("ldfm.toml")).unwrap();
+    assert!(!config.contains(".vimrc"), "{config}");
+
+    // A symlink into the repository, like the ones GNU Stow creates.
+    std::fs::remove_file(laptop.path(".bashrc")).unwrap();
+    std::os::unix::fs::symlink(laptop.repo().join(".bashrc"), laptop.path(".bashrc")).unwrap();
+    std::fs::write(laptop.repo().join("notes.md"), "unrelated\n").unwrap();
+    let output = laptop.ldfm(["untrack", "--purge", "--restore", "~/.bashrc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Replaced symlink"), "{stderr}");
+    assert!(stderr.contains("Deleted repository copy"), "{stderr}");
+    assert!(!laptop.path(".bashrc").is_symlink());
+    assert_eq!(laptop.read(".bashrc"), "alias ll='ls -l'\n");
+    assert!(!laptop.repo().join(".bashrc").exists());
+    assert_eq!(laptop.commits()[0], "Untracked .bashrc.");
+    // Other changes in the repository are left uncommitted.
+    let status = git(&laptop.repo(), ["status", "--porcelain"]);
+    assert_eq!(String::from_utf8_lossy(&status.stdout), "?? notes.md\n");
+}
+
+#[test]
+fn symlinks_are_tracked_at_their_target() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write("shared/vimrc", "set number\n");
+    std::os::unix::fs::symlink(laptop.path("shared/vimrc"), laptop.path(".vimrc")).unwrap();
+    laptop.ldfm(["track", "~/.vimrc"]);
+    let config = std::fs::read_to_string(laptop.repo().join("ldfm.toml")).unwrap();
+    assert!(config.contains("\"~/shared/vimrc\""), "{config}");
+
+    laptop.ldfm(["untrack", "~/.vimrc"]);
+    let config = std::fs::read_to_string(laptop.repo().join("ldfm.toml")).unwrap();
+    assert!(!config.contains("vimrc"), "{config}");
+}
+
+#[test]
+fn higher_priority_repo_wins_in_layered_apply() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "company",
+        "--local-path",
+        "~/company",
+    ]);
+    laptop.ldfm([
+        "init",
+        "--new",
+        "--name",
+        "personal",
+        "--priority",
+        "10",
+        "--local-path",
+        "~/personal",
+    ]);
+    laptop.write(".config/foo/a.conf", "company\n");
+    laptop.ldfm(["--repo", "company", "track", "~/.config/foo"]);
+    laptop.ldfm(["commit"]);
+    laptop.write(".config/foo/a.conf", "live\n");
+    laptop.ldfm(["--repo", "personal", "track", "~/.config/foo/a.conf"]);
+    laptop.ldfm(["--repo", "personal", "commit"]);
+
+    // The live file already matches the personal copy, which is applied after the company one.
+    laptop.ldfm(["apply"]);
+    assert_eq!(laptop.read(".config/foo/a.conf"), "live\n");
+}
+
+#[test]
+fn commit_skips_ignored_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    let gitignore = laptop.repo().join(".gitignore");
+    let mut ignored = std::fs::read_to_string(&gitignore).unwrap_or_default();
+    ignored.push_str(".histfile\n*.log\n");
+    std::fs::write(&gitignore, ignored).unwrap();
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".histfile", ": 1700000000:0;ls\n");
+    laptop.write(".config/app/app.conf", "verbose = true\n");
+    laptop.write(".config/app/debug.log", "started\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.histfile"]);
+    laptop.ldfm(["track", "~/.config/app"]);
+
+    let output = laptop.ldfm(["--format", "json", "commit"]);
+    let outcomes: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let outcomes = outcomes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|outcome| {
+            format!(
+                "{}={} {}",
+                outcome["key"].as_str().unwrap(),
+                outcome["status"].as_str().unwrap(),
+                outcome["reason"].as_str().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            ".bashrc=created ",
+            ".histfile=skipped ignored by git",
+            "app=created some files are ignored by git",
+        ]
+    );
+}
+
+#[test]
+fn sync_summary_collects_failures() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".vimrc", "set number\n");
+    laptop.write(".config/gh/config.yml", "aliases:\n  co: pr checkout\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.vimrc"]);
+    laptop.ldfm(["track", "~/.config/gh/config.yml", "--merge", "aliases"]);
+    let statuses = |output: std::process::Output| {
+        let outcomes: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        outcomes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|outcome| {
+                let key = outcome["key"].as_str().unwrap();
+                format!("{}={}", key, outcome["status"].as_str().unwrap())
+            })
+            .collect::<Vec<_>>()
+    };
+    let commit = laptop.ldfm(["--format", "json", "commit", "--push"]);
+    assert_eq!(
+        statuses(commit),
+        [".bashrc=created", ".vimrc=created", "config.yml=created"]
+    );
+
+    // A broken entry doesn't stop the others from being committed.
+    laptop.write(".bashrc", "alias ll='ls -la'\n");
+    laptop.write(".config/gh/config.yml", "aliases: [\n");
+    let output = laptop
+        .command(["--format", "json", "commit", "--push"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(stderr.contains("default: config.yml"), "{stderr}");
+    assert_eq!(
+        statuses(output),
+        [".bashrc=updated", ".vimrc=unchanged", "config.yml=failed"]
+    );
+    assert_eq!(env.remote_file(".bashrc"), "alias ll='ls -la'\n");
+    assert_eq!(
+        env.remote_file("config.yml"),
+        "aliases:\n  co: pr checkout\n"
+    );
+
+    // All entries that can't be applied are reported, and nothing is applied.
+    let server = env.machine("server");
+    server.write(".bashrc/aliases", "alias ll='ls'\n");
+    server.write(".vimrc/colors.vim", "colorscheme desert\n");
+    server.ldfm(["init", &env.remote_url()]);
+    let output = server
+        .command(["--format", "json", "apply"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(stderr.contains("default: .bashrc"), "{stderr}");
+    assert!(stderr.contains("default: .vimrc"), "{stderr}");
+    assert_eq!(
+        statuses(output),
+        [".bashrc=failed", ".vimrc=failed", "config.yml=skipped"]
+    );
+    assert!(!server.path(".config/gh/config.yml").exists());
+
+    std::fs::remove_dir_all(server.path(".bashrc")).unwrap();
+    std::fs::remove_dir_all(server.path(".vimrc")).unwrap();
+    server.write(".vimrc", "set number\n");
+    let apply = server.ldfm(["--format", "json", "apply", "--no-pull"]);
+    assert_eq!(
+        statuses(apply),
+        [".bashrc=created", ".vimrc=unchanged", "config.yml=created"]
+    );
+}
+
+#[test]
+fn parallel_directory_sync() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    for i in 0..50 {
+        laptop.write(
+            &format!(".config/nvim/lua/plugin{i}.lua"),
+            &format!("-- plugin {i}\n"),
+        );
+    }
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["commit", "--push", "--jobs", "4"]);
+    assert_eq!(env.remote_file("nvim/lua/plugin42.lua"), "-- plugin 42\n");
+
+    let server = env.machine("server");
+    server.write(".config/nvim/lua/plugin7.lua", "-- local change\n");
+    server.ldfm(["init", &env.remote_url()]);
+    let output = server.ldfm(["--format", "table", "apply", "--jobs", "4"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "REPO     KEY      STATUS   SIZE   REASON\n\
+         default  .bashrc  created  17 B\n\
+         default  nvim     updated  640 B\n"
+    );
+    for i in 0..50 {
+        assert_eq!(
+            server.read(&format!(".config/nvim/lua/plugin{i}.lua")),
+            format!("-- plugin {i}\n")
+        );
+    }
+}
+
+#[test]
+fn reserved_file_names_get_prefixed_keys() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".gitignore", "*.log\n");
+    laptop.write(".config/app/debug.log", "verbose = true\n");
+    let output = laptop.ldfm(["track", "~/.gitignore"]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("0-.gitignore"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    laptop.ldfm(["track", "~/.config/app"]);
+    laptop.ldfm(["commit", "--push"]);
+
+    assert_eq!(env.remote_file("0-.gitignore"), "*.log\n");
+    assert!(!env.remote_file(".gitignore").contains("*.log"));
+    assert_eq!(env.remote_file("app/debug.log"), "verbose = true\n");
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestEnv;
+
+/// Wait until the condition holds or fail after a few seconds.
+fn wait_for(mut condition: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(
+            started.elapsed() < Duration::from_secs(15),
+            "Timed out waiting for watch"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn commits_changed_entries() {
+    let env = TestEnv::new();
+    let laptop = env.machine("laptop");
+    laptop.ldfm(["init", "--new", &env.remote_url()]);
+    laptop.write(".bashrc", "alias ll='ls -l'\n");
+    laptop.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    laptop.ldfm(["track", "~/.bashrc"]);
+    laptop.ldfm(["track", "~/.config/nvim"]);
+    let repo_config = laptop.repo().join("ldfm.toml");
+    let contents = std::fs::read_to_string(&repo_config).unwrap();
+    std::fs::write(&repo_config, format!("exclude = [\"*.log\"]\n{contents}")).unwrap();
+    laptop.ldfm(["commit", "--push"]);
+
+    let mut watch = laptop
+        .command(["watch", "--debounce", "1", "--push-interval", "1"])
+        .spawn()
+        .unwrap();
+    // Give the watcher time to start.
+    std::thread::sleep(Duration::from_secs(1));
+    laptop.write(".config/nvim/init.lua", "vim.o.number = false\n");
+    laptop.write(".config/nvim/debug.log", "noise\n");
+    wait_for(|| env.remote_file("nvim/init.lua") == "vim.o.number = false\n");
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+
+    assert_eq!(laptop.commits()[0], "Dotfiles sync: nvim.");
+    assert!(!env.remote_files().contains(&"nvim/debug.log".to_string()));
+    // Untouched entries stay as they were.
+    assert_eq!(env.remote_file(".bashrc"), "alias ll='ls -l'\n");
+}